@@ -0,0 +1,63 @@
+//! Counts the allocations made by 10k small `push_slice` calls, with and without a copy
+//! pool.
+//!
+//! Run with `cargo bench --bench push_slice`.
+
+extern crate byte_channel;
+extern crate futures;
+
+use byte_channel::sync;
+use futures::Stream;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+const PUSHES: usize = 10_000;
+const PUSH_SIZE: usize = 48;
+
+fn run(pool_size: usize) {
+    let (wx, mut tx, rx) = sync::new::<()>(PUSHES * PUSH_SIZE);
+    let mut wx = wx.wait();
+    wx.next().expect("window").expect("window");
+    tx.set_copy_pool_size(pool_size);
+    let src = [7u8; PUSH_SIZE];
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..PUSHES {
+        tx.push_slice(&src).expect("push");
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    println!(
+        "pool size {:>5}: {:>6} allocations, {:?}",
+        pool_size,
+        allocations,
+        elapsed
+    );
+    drop(rx);
+}
+
+fn main() {
+    run(0);
+    run(4096);
+}
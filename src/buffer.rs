@@ -1,36 +1,50 @@
-use bytes::Bytes;
 use futures::task::Task;
-use std::collections::VecDeque;
+
+use rope::Rope;
 
 /// The shared state of the byte channel.
-//
-/// TODO `buffers` should be stored as a Rope, which should also back Chunk.
 #[derive(Debug)]
 pub enum ChannelBuffer<E> {
     Sending {
-        len: usize,
-        buffers: VecDeque<Bytes>,
+        buffers: Rope,
         awaiting_chunk: Option<Task>,
     },
 
     /// No more data may be added to the byte channel.
     SenderClosed {
-        len: usize,
-        buffers: VecDeque<Bytes>,
+        buffers: Rope,
     },
 
     /// Indicates the sender has failed the stream and the next chunk read will fail with
     /// `E`.
     SenderFailed(E),
 
+    /// Indicates the sender has failed the stream after the buffered data, which must be
+    /// read before the next chunk read fails with `error`.
+    DrainingThenFailed {
+        buffers: Rope,
+        error: E,
+    },
+
+    /// Indicates the receiver has cancelled the stream. The error is held until a sender
+    /// takes it.
+    ReceiverCancelled(Option<E>),
+
+    /// Indicates the receiver has failed the stream. The error is held until a sender
+    /// takes it.
+    ReceiverFailed(Option<E>),
+
+    /// Indicates the receiver will not read any more data, though it has not been
+    /// dropped.
+    ReceiverDone,
+
     LostReceiver,
 }
 
 impl<E> Default for ChannelBuffer<E> {
     fn default() -> Self {
         ChannelBuffer::Sending {
-            len: 0,
-            buffers: VecDeque::new(),
+            buffers: Rope::new(),
             awaiting_chunk: None,
         }
     }
@@ -40,8 +54,9 @@ impl<E> ChannelBuffer<E> {
     pub fn len(&self) -> usize {
         use self::ChannelBuffer::*;
         match *self {
-            Sending { len, .. } => len,
-            SenderClosed { len, .. } => len,
+            Sending { ref buffers, .. } |
+            SenderClosed { ref buffers, .. } |
+            DrainingThenFailed { ref buffers, .. } => buffers.len(),
             _ => 0,
         }
     }
@@ -49,4 +64,44 @@ impl<E> ChannelBuffer<E> {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// The number of segments buffered.
+    pub fn segment_count(&self) -> usize {
+        use self::ChannelBuffer::*;
+        match *self {
+            Sending { ref buffers, .. } |
+            SenderClosed { ref buffers, .. } |
+            DrainingThenFailed { ref buffers, .. } => buffers.segment_count(),
+            _ => 0,
+        }
+    }
+
+    /// Converts the error held by the buffer, if any, with `f`.
+    pub fn map_err<F, G>(self, f: G) -> ChannelBuffer<F>
+    where
+        G: FnOnce(E) -> F,
+    {
+        use self::ChannelBuffer::*;
+        match self {
+            Sending { buffers, awaiting_chunk } => Sending { buffers, awaiting_chunk },
+            SenderClosed { buffers } => SenderClosed { buffers },
+            SenderFailed(e) => SenderFailed(f(e)),
+            DrainingThenFailed { buffers, error } => DrainingThenFailed {
+                buffers,
+                error: f(error),
+            },
+            ReceiverCancelled(e) => ReceiverCancelled(e.map(f)),
+            ReceiverFailed(e) => ReceiverFailed(e.map(f)),
+            ReceiverDone => ReceiverDone,
+            LostReceiver => LostReceiver,
+        }
+    }
+
+    /// Indicates whether a receiver is parked waiting for more data.
+    pub fn is_awaiting_chunk(&self) -> bool {
+        match *self {
+            ChannelBuffer::Sending { ref awaiting_chunk, .. } => awaiting_chunk.is_some(),
+            _ => false,
+        }
+    }
 }
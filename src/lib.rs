@@ -1,12 +1,117 @@
 extern crate bytes;
-#[cfg_attr(test, macro_use)]
+#[cfg(feature = "checksum")]
+extern crate crc32fast;
 extern crate futures;
+#[cfg(feature = "futures03")]
+extern crate futures_core;
+extern crate iovec;
+#[cfg(feature = "metrics")]
+extern crate metrics;
+#[cfg(feature = "serde")]
+extern crate serde;
 #[cfg(test)]
 extern crate test_futures;
+#[cfg(feature = "tokio-io")]
+extern crate tokio_io;
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
 
 mod buffer;
+mod rope;
 pub mod sync;
 mod window;
 
-#[derive(Copy, Clone, Debug)]
+pub use window::{NotifyThreshold, WindowSnapshot};
+#[cfg(feature = "record")]
+pub use window::{Op, Recorded};
+
+/// Indicates that a channel's receiver has been dropped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct LostReceiver;
+
+impl fmt::Display for LostReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("byte channel receiver was dropped")
+    }
+}
+
+impl Error for LostReceiver {}
+
+/// Indicates why bytes could not be pushed into a channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PushError {
+    /// The receiver has been dropped.
+    LostReceiver,
+
+    /// The channel has been closed or reset by another sender.
+    Closed,
+
+    /// The receiver has cancelled the stream.
+    Cancelled,
+
+    /// The receiver has failed the stream.
+    ReceiverFailed,
+
+    /// The receiver has finished reading, though it has not been dropped.
+    ReceiverDone,
+
+    /// The bytes exceed the channel's maximum message size, as configured by
+    /// `Builder::max_message`.
+    MessageTooLarge,
+
+    /// The bytes exceed the channel's advertised window.
+    Overflow { available: usize, needed: usize },
+
+    /// The bytes would exceed the total number of bytes the channel may carry, as
+    /// configured by `Builder::total_limit`. If the channel was created by
+    /// `sync::new_with_limit`, the stream has been failed.
+    LimitExceeded,
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PushError::LostReceiver => LostReceiver.fmt(f),
+            PushError::Closed => f.write_str("byte channel was closed by another sender"),
+            PushError::Cancelled => f.write_str("byte channel was cancelled by its receiver"),
+            PushError::ReceiverFailed => f.write_str("byte channel was failed by its receiver"),
+            PushError::ReceiverDone => f.write_str("byte channel receiver finished reading"),
+            PushError::MessageTooLarge => f.write_str("message exceeds byte channel maximum"),
+            PushError::LimitExceeded => f.write_str("push exceeds byte channel total limit"),
+            PushError::Overflow { available, needed } => write!(
+                f,
+                "push of {} bytes exceeds byte channel window of {} bytes",
+                needed, available
+            ),
+        }
+    }
+}
+
+impl Error for PushError {}
+
+/// An error that cannot occur.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Never {}
+
+/// Indicates that a read deadline passed before data was available.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimedOut;
+
+/// Indicates that a channel carried more bytes than a `LimitReceiver` allows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LimitExceeded;
+
+/// Indicates that a receiver waited on a channel that had no window available to the
+/// sender.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stalled;
+
+/// Indicates that a channel's receiver and sender are both parked, each waiting on the
+/// other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Deadlock {
+    /// How long the channel has been observed in this state.
+    pub parked_for: Duration,
+}
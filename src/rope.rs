@@ -0,0 +1,259 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{VecDeque, vec_deque};
+
+/// A sequence of `Bytes` segments, read as one contiguous byte sequence.
+///
+/// Segments are never copied: splitting a rope divides at most one segment, sharing its
+/// storage between the two halves.
+#[derive(Clone, Debug, Default)]
+pub struct Rope {
+    len: usize,
+    segments: VecDeque<Bytes>,
+}
+
+impl Rope {
+    pub fn new() -> Rope {
+        Rope::default()
+    }
+
+    /// The total length of all segments.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn front(&self) -> Option<&Bytes> {
+        self.segments.front()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, Bytes> {
+        self.segments.iter()
+    }
+
+    /// Appends a segment. Empty segments are discarded.
+    pub fn push_back(&mut self, bytes: Bytes) {
+        if !bytes.is_empty() {
+            self.len += bytes.len();
+            self.segments.push_back(bytes);
+        }
+    }
+
+    /// Appends a segment, joining it onto the last segment if the two together are no
+    /// larger than `limit` bytes.
+    ///
+    /// The last segment is extended in place when it isn't shared. Otherwise, it is
+    /// copied into a new buffer with room for `limit` bytes, which later appends fill.
+    pub fn push_back_coalesced(&mut self, bytes: Bytes, limit: usize) {
+        let fits = match self.segments.back() {
+            Some(last) => last.len() + bytes.len() <= limit,
+            None => false,
+        };
+        if !fits || bytes.is_empty() {
+            self.push_back(bytes);
+            return;
+        }
+
+        let last = self.segments.pop_back().expect("rope must not be empty");
+        let mut joined = last.try_mut().unwrap_or_else(|last| {
+            let mut joined = BytesMut::with_capacity(limit);
+            joined.put_slice(&last);
+            joined
+        });
+        joined.extend_from_slice(&bytes);
+        self.len += bytes.len();
+        self.segments.push_back(joined.freeze());
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let bytes = self.segments.pop_front()?;
+        self.len -= bytes.len();
+        Some(bytes)
+    }
+
+    /// Removes the first `sz` bytes, returning them as a new rope.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `sz` exceeds the rope's length.
+    pub fn split_to(&mut self, mut sz: usize) -> Rope {
+        assert!(sz <= self.len, "split exceeds rope length");
+        let mut front = Rope::new();
+        while 0 < sz {
+            let mut bytes = self.pop_front().expect("rope must not be empty");
+            if sz < bytes.len() {
+                // Return the beginning of the segment and put the rest of it back.
+                let head = bytes.split_to(sz);
+                self.len += bytes.len();
+                self.segments.push_front(bytes);
+                front.push_back(head);
+                break;
+            }
+            sz -= bytes.len();
+            front.push_back(bytes);
+        }
+        front
+    }
+
+    /// Removes the last `sz` bytes, returning them as a new rope whose segments are in
+    /// reverse order, i.e. the most recent segment first.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `sz` exceeds the rope's length.
+    pub fn split_back(&mut self, mut sz: usize) -> Rope {
+        assert!(sz <= self.len, "split exceeds rope length");
+        let mut back = Rope::new();
+        while 0 < sz {
+            let mut bytes = self.segments.pop_back().expect("rope must not be empty");
+            self.len -= bytes.len();
+            if sz < bytes.len() {
+                let tail = bytes.split_off(bytes.len() - sz);
+                self.len += bytes.len();
+                self.segments.push_back(bytes);
+                back.push_back(tail);
+                break;
+            }
+            sz -= bytes.len();
+            back.push_back(bytes);
+        }
+        back
+    }
+
+    /// Discards the first `sz` bytes.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `sz` exceeds the rope's length.
+    pub fn advance(&mut self, sz: usize) {
+        drop(self.split_to(sz));
+    }
+
+    /// Joins the segments into a single contiguous `Bytes`, copying unless there is at
+    /// most one segment.
+    pub fn into_bytes(mut self) -> Bytes {
+        if self.segments.len() <= 1 {
+            return self.segments.pop_front().unwrap_or_default();
+        }
+
+        let mut bytes = BytesMut::with_capacity(self.len);
+        for b in &self.segments {
+            bytes.put_slice(b);
+        }
+        bytes.freeze()
+    }
+}
+
+impl IntoIterator for Rope {
+    type Item = Bytes;
+    type IntoIter = vec_deque::IntoIter<Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Rope {
+    type Item = &'a Bytes;
+    type IntoIter = vec_deque::Iter<'a, Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::Rope;
+
+    fn rope(segments: &[&'static str]) -> Rope {
+        let mut rope = Rope::new();
+        for s in segments {
+            rope.push_back(Bytes::from(*s));
+        }
+        rope
+    }
+
+    fn segments(rope: &Rope) -> Vec<&[u8]> {
+        rope.iter().map(|b| b.as_ref()).collect()
+    }
+
+    #[test]
+    fn push_tracks_length_and_skips_empty_segments() {
+        let mut r = rope(&["01", "", "234"]);
+        assert_eq!(r.len(), 5);
+        assert_eq!(r.segment_count(), 2);
+        assert_eq!(r.pop_front(), Some(Bytes::from("01")));
+        assert_eq!(r.len(), 3);
+    }
+
+    #[test]
+    fn push_coalesced_joins_small_segments() {
+        let mut r = Rope::new();
+        r.push_back_coalesced(Bytes::from("01"), 4);
+        r.push_back_coalesced(Bytes::from("2"), 4);
+        r.push_back_coalesced(Bytes::from("3"), 4);
+        r.push_back_coalesced(Bytes::from("4"), 4);
+        r.push_back_coalesced(Bytes::from("56789"), 4);
+        r.push_back_coalesced(Bytes::from("ab"), 4);
+        assert_eq!(segments(&r), vec![&b"0123"[..], b"4", b"56789", b"ab"]);
+        assert_eq!(r.len(), 12);
+
+        // A segment shared with a split is copied rather than extended.
+        let mut r = rope(&["01"]);
+        let front = r.split_to(1);
+        r.push_back_coalesced(Bytes::from("2"), 4);
+        assert_eq!(segments(&front), vec![&b"0"[..]]);
+        assert_eq!(segments(&r), vec![&b"12"[..]]);
+    }
+
+    #[test]
+    fn split_to_divides_segments() {
+        let mut r = rope(&["01", "234", "56"]);
+        let front = r.split_to(3);
+        assert_eq!(segments(&front), vec![&b"01"[..], b"2"]);
+        assert_eq!(front.len(), 3);
+        assert_eq!(segments(&r), vec![&b"34"[..], b"56"]);
+        assert_eq!(r.len(), 4);
+
+        let front = r.split_to(2);
+        assert_eq!(segments(&front), vec![&b"34"[..]]);
+        assert_eq!(segments(&r), vec![&b"56"[..]]);
+
+        assert!(r.split_to(0).is_empty());
+        r.advance(2);
+        assert!(r.is_empty());
+        assert_eq!(r.segment_count(), 0);
+    }
+
+    #[test]
+    fn split_back_returns_most_recent_first() {
+        let mut r = rope(&["01", "234", "56"]);
+        let back = r.split_back(4);
+        assert_eq!(segments(&back), vec![&b"56"[..], b"34"]);
+        assert_eq!(back.len(), 4);
+        assert_eq!(segments(&r), vec![&b"01"[..], b"2"]);
+        assert_eq!(r.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "split exceeds rope length")]
+    fn split_past_end_panics() {
+        rope(&["01"]).split_to(3);
+    }
+
+    #[test]
+    fn into_bytes_copies_only_multiple_segments() {
+        assert_eq!(Rope::new().into_bytes(), Bytes::new());
+        assert_eq!(rope(&["012"]).into_bytes(), Bytes::from("012"));
+        assert_eq!(rope(&["01", "234"]).into_bytes(), Bytes::from("01234"));
+    }
+}
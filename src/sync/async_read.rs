@@ -0,0 +1,52 @@
+use futures::*;
+use std::io::{self, Read};
+#[cfg(feature = "tokio-io")]
+use tokio_io::AsyncRead;
+
+use super::ByteReceiver;
+
+pub fn new<E>(receiver: ByteReceiver<E>) -> AsyncReader<E> {
+    AsyncReader(receiver)
+}
+
+/// Reads a channel's data through `std::io::Read` and, with the `tokio-io` feature,
+/// `tokio_io::AsyncRead`.
+///
+/// Reads are served by `ByteReceiver::poll_read_into`, so bytes left over from a chunk
+/// are retained for the next read, and capacity is returned to the window as bytes are
+/// copied out. A read that would block returns `io::ErrorKind::WouldBlock` and saves the
+/// current task to be notified when data is pushed. Channel failures are converted into
+/// `io::Error`s.
+///
+/// Created by `ByteReceiver::into_async_read`.
+#[derive(Debug)]
+pub struct AsyncReader<E>(ByteReceiver<E>);
+
+impl<E> AsyncReader<E> {
+    pub fn get_ref(&self) -> &ByteReceiver<E> {
+        &self.0
+    }
+
+    pub fn get_mut(&mut self) -> &mut ByteReceiver<E> {
+        &mut self.0
+    }
+
+    /// Returns the underlying receiver, which retains any bytes left over from a
+    /// partially read chunk.
+    pub fn into_inner(self) -> ByteReceiver<E> {
+        self.0
+    }
+}
+
+impl<E: Into<io::Error>> Read for AsyncReader<E> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        match self.0.poll_read_into(dst) {
+            Ok(Async::Ready(sz)) => Ok(sz),
+            Ok(Async::NotReady) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl<E: Into<io::Error>> AsyncRead for AsyncReader<E> {}
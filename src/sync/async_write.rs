@@ -0,0 +1,88 @@
+use futures::*;
+use std::cmp;
+use std::io::{self, Write};
+#[cfg(feature = "tokio-io")]
+use tokio_io::AsyncWrite;
+
+use PushError;
+use super::ByteSender;
+
+pub fn new<E>(sender: ByteSender<E>) -> AsyncWriter<E> {
+    AsyncWriter(sender)
+}
+
+/// Writes into a channel through `std::io::Write` and, with the `tokio-io` feature,
+/// `tokio_io::AsyncWrite`.
+///
+/// Each write copies as much of the caller's slice as the window allows. A write that
+/// would block returns `io::ErrorKind::WouldBlock` and saves the current task to be
+/// notified when capacity is advertised. Once the channel's total limit has been
+/// reached, writes fail the channel as other pushes beyond the limit do. Failed pushes
+/// are reported as `io::Error`s.
+///
+/// Flushing does nothing, since pushed bytes are immediately available to the receiver.
+/// Shutting down closes the channel, as `ByteSender::close` does.
+///
+/// Created by `ByteSender::into_async_write`.
+#[derive(Debug)]
+pub struct AsyncWriter<E>(ByteSender<E>);
+
+impl<E> AsyncWriter<E> {
+    pub fn get_ref(&self) -> &ByteSender<E> {
+        &self.0
+    }
+
+    pub fn get_mut(&mut self) -> &mut ByteSender<E> {
+        &mut self.0
+    }
+
+    pub fn into_inner(self) -> ByteSender<E> {
+        self.0
+    }
+}
+
+impl<E> Write for AsyncWriter<E> {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        if src.is_empty() {
+            return Ok(0);
+        }
+
+        let available = match self.0.poll_capacity() {
+            Ok(Async::Ready(sz)) => sz,
+            Ok(Async::NotReady) => return Err(io::ErrorKind::WouldBlock.into()),
+            // The push fails as any push beyond the limit does.
+            Err(PushError::LimitExceeded) => src.len(),
+            Err(e) => return Err(into_io_error(e)),
+        };
+        let sz = cmp::min(src.len(), available);
+        if sz == 0 {
+            return Ok(0);
+        }
+        self.0.push_slice(&src[..sz]).map_err(into_io_error)?;
+        Ok(sz)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl<E> AsyncWrite for AsyncWriter<E> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.0.do_close();
+        Ok(Async::Ready(()))
+    }
+}
+
+fn into_io_error(e: PushError) -> io::Error {
+    let kind = match e {
+        PushError::LostReceiver |
+        PushError::Closed |
+        PushError::Cancelled |
+        PushError::ReceiverFailed |
+        PushError::ReceiverDone => io::ErrorKind::BrokenPipe,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, e)
+}
@@ -0,0 +1,138 @@
+//! A channel that delivers every pushed byte to several receivers.
+
+use bytes::Bytes;
+use futures::*;
+use std::cmp;
+
+use PushError;
+use super::{ByteReceiver, ByteSender, bounded};
+
+/// Creates a channel whose pushes are delivered to each of `n_receivers` receivers.
+///
+/// Each receiver reads from its own channel, with a window of `initial_window_size`
+/// bytes that is managed as by `sync::bounded`: capacity is returned as soon as the
+/// receiver consumes its chunks. Pushed `Bytes` are shared by all receivers, so
+/// broadcasting copies nothing.
+///
+/// The sender's window is the smallest of the receivers' windows, so a receiver that
+/// consumes slowly holds back all of the others. Once a receiver is dropped, its window
+/// no longer limits the sender.
+pub fn new<E>(
+    initial_window_size: usize,
+    n_receivers: usize,
+) -> (BroadcastSender<E>, Vec<ByteReceiver<E>>) {
+    let mut senders = Vec::with_capacity(n_receivers);
+    let mut receivers = Vec::with_capacity(n_receivers);
+    for _ in 0..n_receivers {
+        let (tx, rx) = bounded(initial_window_size);
+        senders.push(tx);
+        receivers.push(rx);
+    }
+    (BroadcastSender { senders }, receivers)
+}
+
+/// Pushes bytes to every receiver of a broadcast channel.
+///
+/// Created by `sync::broadcast::new`. The channel is closed for all receivers when the
+/// sender is closed or dropped.
+#[derive(Debug)]
+pub struct BroadcastSender<E> {
+    senders: Vec<ByteSender<E>>,
+}
+
+impl<E> BroadcastSender<E> {
+    /// The number of receivers that have not been dropped.
+    pub fn receivers(&self) -> usize {
+        self.senders.iter().filter(|tx| !tx.is_receiver_lost()).count()
+    }
+
+    /// The number of bytes that may be pushed, i.e. the smallest window available among
+    /// the receivers that have not been dropped.
+    pub fn available_window(&self) -> usize {
+        self.senders
+            .iter()
+            .filter(|tx| !tx.is_receiver_lost())
+            .map(|tx| tx.available_window())
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Polls for window capacity on every receiver, returning the number of bytes that
+    /// may be pushed.
+    ///
+    /// If any receiver's window is exhausted, the current task is notified when capacity
+    /// is returned to it. Fails with `PushError::LostReceiver` once all receivers have
+    /// been dropped.
+    pub fn poll_capacity(&mut self) -> Poll<usize, PushError> {
+        let mut available = usize::MAX;
+        let mut ready = true;
+        let mut i = 0;
+        while i < self.senders.len() {
+            match self.senders[i].poll_capacity() {
+                Ok(Async::Ready(sz)) => available = cmp::min(available, sz),
+                Ok(Async::NotReady) => ready = false,
+                Err(PushError::LostReceiver) => {
+                    self.senders.swap_remove(i);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            i += 1;
+        }
+
+        if self.senders.is_empty() {
+            Err(PushError::LostReceiver)
+        } else if ready {
+            Ok(Async::Ready(available))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    /// Pushes `bytes` to every receiver that has not been dropped.
+    ///
+    /// Fails with `PushError::LostReceiver` once all receivers have been dropped. If any
+    /// receiver has cancelled or half-closed its channel, the push fails with the
+    /// corresponding error and no receiver is sent the bytes.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `bytes` exceeds `available_window`.
+    pub fn push_bytes(&mut self, bytes: Bytes) -> Result<(), PushError> {
+        self.senders.retain(|tx| !tx.is_receiver_lost());
+        if self.senders.is_empty() {
+            return Err(PushError::LostReceiver);
+        }
+        if self.available_window() < bytes.len() {
+            panic!("byte channel overflow");
+        }
+
+        for tx in &self.senders {
+            match tx.check_accepting() {
+                Ok(()) | Err(PushError::LostReceiver) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        for tx in &mut self.senders {
+            match tx.push_bytes(bytes.clone()) {
+                // A receiver that stopped reading since it was checked no longer needs
+                // the bytes. Its failure is reported by the next push.
+                Ok(_) |
+                Err(PushError::LostReceiver) |
+                Err(PushError::Cancelled) |
+                Err(PushError::ReceiverFailed) |
+                Err(PushError::ReceiverDone) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the channel for every receiver.
+    pub fn close(self) {
+        for tx in self.senders {
+            tx.close();
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::cmp;
+use std::sync::{Arc, Mutex};
+
+use buffer::ChannelBuffer;
+use window::{NotifyThreshold, Window};
+use super::{ByteReceiver, ByteSender, WindowAdvertiser, receiver, sender, window};
+use super::stats::Stats;
+
+/// Configures and creates a byte channel.
+///
+/// `sync::new(sz)` is equivalent to `Builder::new().window_size(sz).build()`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Builder {
+    window_size: usize,
+    burst_size: usize,
+    notify_threshold: Option<NotifyThreshold>,
+    total_limit: Option<usize>,
+    push_after_close: PushAfterClose,
+    warn_buffered_above: Option<(usize, fn(usize))>,
+    max_message: Option<usize>,
+    coalesce_limit: Option<usize>,
+    collect_stats: bool,
+    lifo: bool,
+    #[cfg(feature = "metrics")]
+    label: &'static str,
+}
+
+/// Determines what happens when bytes are pushed into a channel that another sender has
+/// already closed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PushAfterClose {
+    /// The push fails with `PushError::Closed`.
+    #[default]
+    Error,
+
+    /// The bytes are silently discarded.
+    Ignore,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the channel's initial window size.
+    ///
+    /// Defaults to 0.
+    pub fn window_size(mut self, sz: usize) -> Self {
+        self.window_size = sz;
+        self
+    }
+
+    /// Sets a larger window to be advertised initially.
+    ///
+    /// Once the sender has used `sz - window_size` bytes of the burst, the window settles
+    /// at the size set by `window_size`. Bursts smaller than the window size are ignored.
+    /// Defaults to no burst.
+    pub fn burst_window_size(mut self, sz: usize) -> Self {
+        self.burst_size = sz;
+        self
+    }
+
+    /// Delays waking a blocked `WindowAdvertiser` until the capacity returned by the
+    /// receiver reaches `threshold`.
+    ///
+    /// Ratios are relative to the window size. Defaults to notifying as soon as any
+    /// capacity is returned.
+    pub fn notify_threshold(mut self, threshold: NotifyThreshold) -> Self {
+        self.notify_threshold = Some(threshold);
+        self
+    }
+
+    /// Limits the total number of bytes the channel may carry over its lifetime.
+    ///
+    /// The senders' available window never exceeds the bytes remaining under the limit,
+    /// and pushes that would exceed it fail with `PushError::LimitExceeded`, leaving the
+    /// stream intact. To also fail the receiver, use `sync::new_with_limit`. Defaults to
+    /// no limit.
+    pub fn total_limit(mut self, limit: usize) -> Self {
+        self.total_limit = Some(limit);
+        self
+    }
+
+    /// Sets how senders handle pushes after the channel has been closed.
+    ///
+    /// Defaults to `PushAfterClose::Error`.
+    pub fn on_push_after_close(mut self, policy: PushAfterClose) -> Self {
+        self.push_after_close = policy;
+        self
+    }
+
+    /// Rejects pushes larger than `sz` bytes with `PushError::MessageTooLarge`.
+    ///
+    /// This is intended for channels that carry one framed message per push. Defaults to
+    /// no limit.
+    pub fn max_message(mut self, sz: usize) -> Self {
+        self.max_message = Some(sz);
+        self
+    }
+
+    /// Calls `warn` with the buffered length whenever a push causes more than
+    /// `threshold` bytes to be buffered, indicating that the window is larger than the
+    /// consumer can drain.
+    ///
+    /// `warn` is called once each time the threshold is crossed, while the channel is
+    /// locked, so it must not use the channel. Defaults to no warning.
+    pub fn warn_buffered_above(mut self, threshold: usize, warn: fn(usize)) -> Self {
+        self.warn_buffered_above = Some((threshold, warn));
+        self
+    }
+
+    /// Joins small pushes into buffered segments of up to `limit` bytes, so that a
+    /// sender making many small pushes doesn't leave the receiver to assemble chunks from
+    /// many small segments.
+    ///
+    /// A push is copied onto the last buffered segment if the two together fit within
+    /// `limit`; larger pushes are buffered as they are. Window accounting is unaffected.
+    /// Since pushes no longer map to segments, this should not be combined with `lifo`.
+    /// Defaults to buffering each push as a separate segment.
+    pub fn coalesce_pushes(mut self, limit: usize) -> Self {
+        self.coalesce_limit = Some(limit);
+        self
+    }
+
+    /// Causes the receiver's `poll_chunk` to return the most recently pushed bytes first.
+    ///
+    /// This breaks byte-stream ordering and is only suitable for protocols that frame
+    /// each push as a message and read whole messages. Other reads, e.g. `poll_until`,
+    /// are unaffected. Defaults to false.
+    pub fn lifo(mut self, lifo: bool) -> Self {
+        self.lifo = lifo;
+        self
+    }
+
+    /// Enables the counters returned by `ByteSender::stats` and `ByteReceiver::stats`.
+    ///
+    /// Defaults to false, in which case no counters are maintained.
+    pub fn collect_stats(mut self, enabled: bool) -> Self {
+        self.collect_stats = enabled;
+        self
+    }
+
+    /// Labels the channel's park-time histograms.
+    ///
+    /// Defaults to an empty label.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_label(mut self, label: &'static str) -> Self {
+        self.label = label;
+        self
+    }
+
+    pub fn build<E>(self) -> (WindowAdvertiser, ByteSender<E>, ByteReceiver<E>) {
+        let buffer = Arc::new(Mutex::new(Some(ChannelBuffer::default())));
+        let mut window = Window::with_threshold(
+            self.window_size,
+            cmp::max(self.burst_size, self.window_size),
+            self.notify_threshold.unwrap_or(NotifyThreshold::Bytes(1)),
+        );
+        if let Some(limit) = self.total_limit {
+            window.set_total_limit(limit);
+        }
+        let window = Arc::new(Mutex::new(window));
+
+        let stats = if self.collect_stats {
+            Some(Arc::new(Stats::default()))
+        } else {
+            None
+        };
+
+        #[cfg(feature = "metrics")]
+        let wx = window::new(window.clone(), self.label);
+        #[cfg(not(feature = "metrics"))]
+        let wx = window::new(window.clone());
+        let tx = sender::new(
+            buffer.clone(),
+            window.clone(),
+            stats.clone(),
+            self.push_after_close,
+            self.warn_buffered_above,
+            self.max_message,
+            self.coalesce_limit,
+        );
+        #[cfg(feature = "metrics")]
+        let tx = tx.with_metrics_label(self.label);
+        #[cfg(feature = "metrics")]
+        let mut rx = receiver::new(buffer, window, stats, self.label);
+        #[cfg(not(feature = "metrics"))]
+        let mut rx = receiver::new(buffer, window, stats);
+        rx.set_lifo(self.lifo);
+        (wx, tx, rx)
+    }
+}
@@ -1,13 +1,21 @@
 use bytes::{Buf, Bytes};
-use std::collections::VecDeque;
+use futures::{Async, Stream, stream};
+use iovec::IoVec;
+use std::borrow::Cow;
+use std::cmp;
+use std::mem;
 use std::sync::Arc;
 
+use rope::Rope;
 use super::{SharedWindow, WeakWindow};
+use super::stats::SharedStats;
+use super::super::Never;
 
 pub fn empty() -> Chunk {
     Chunk {
         bytes: ChunkBytes::Zero,
         window: None,
+        stats: None,
     }
 }
 
@@ -19,26 +27,43 @@ pub fn from_bytes(w: &SharedWindow, bytes: Bytes) -> Chunk {
     Chunk {
         bytes: ChunkBytes::One(bytes),
         window: Some(Arc::downgrade(w)),
+        stats: None,
     }
 }
 
-pub fn from_vec(w: &SharedWindow, mut buffers: VecDeque<Bytes>) -> Chunk {
-    let sz = buffers.len();
-    if sz == 0 {
-        return empty();
-    } else if sz == 1 {
-        return from_bytes(w, buffers.pop_front().unwrap());
+pub fn from_rope(w: &SharedWindow, mut buffers: Rope) -> Chunk {
+    match buffers.segment_count() {
+        0 => empty(),
+        1 => from_bytes(w, buffers.pop_front().unwrap()),
+        _ => Chunk {
+            bytes: ChunkBytes::Many(buffers),
+            window: Some(Arc::downgrade(w)),
+            stats: None,
+        },
     }
+}
 
-    let remaining = buffers.iter().fold(0, |sz, b| sz + b.len());
-    if remaining == 0 {
-        return empty();
+/// Counts `chunk` as outstanding in `stats` until it has been dropped.
+pub fn track(mut chunk: Chunk, stats: &SharedStats) -> Chunk {
+    if let Some(ref stats) = *stats {
+        if chunk.holds_credit() {
+            stats.chunk_created();
+            chunk.stats = Some(stats.clone());
+        }
     }
+    chunk
+}
 
-    Chunk {
-        bytes: ChunkBytes::Many { remaining, buffers },
-        window: Some(Arc::downgrade(w)),
-    }
+/// The chunk's first segment, without consuming it, or `None` once the chunk has been
+/// consumed.
+pub fn front_segment(chunk: &Chunk) -> Option<Bytes> {
+    let bytes = match chunk.bytes {
+        ChunkBytes::Zero => None,
+        ChunkBytes::One(ref bytes) => Some(bytes.clone()),
+        ChunkBytes::Many(ref buffers) => buffers.front().cloned(),
+    };
+    // An advanced chunk may retain an empty segment.
+    bytes.filter(|b| !b.is_empty())
 }
 
 /// Stores an immutable byte sequence.  As the sequence is consumed, the window is opened.
@@ -46,44 +71,198 @@ pub fn from_vec(w: &SharedWindow, mut buffers: VecDeque<Bytes>) -> Chunk {
 pub struct Chunk {
     bytes: ChunkBytes,
     window: Option<WeakWindow>,
+    stats: SharedStats,
 }
 
 impl Chunk {
+    /// The number of bytes remaining in the chunk.
+    ///
+    /// This is always equal to `Buf::remaining`, and so decreases as the chunk is
+    /// advanced.
     pub fn len(&self) -> usize {
         match self.bytes {
             ChunkBytes::Zero => 0,
             ChunkBytes::One(ref bytes) => bytes.len(),
-            ChunkBytes::Many { ref remaining, .. } => *remaining,
+            ChunkBytes::Many(ref buffers) => buffers.len(),
         }
     }
 
-    fn add_capacity(wref: &WeakWindow, sz: usize) {
+    /// Indicates whether no bytes remain in the chunk, e.g. because it has been fully
+    /// advanced or was read with a `max_sz` of 0.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Indicates whether consuming or dropping this chunk returns capacity to the window.
+    pub fn holds_credit(&self) -> bool {
+        self.window.is_some() && !self.is_empty()
+    }
+
+    /// Converts the chunk into a single contiguous `Bytes`, returning its capacity to the
+    /// window as a full read would.
+    ///
+    /// A chunk with a single segment is returned without copying. Otherwise, every
+    /// segment is copied into a new buffer, which takes time proportional to the chunk's
+    /// length.
+    pub fn to_bytes(mut self) -> Bytes {
+        let bytes = match mem::replace(&mut self.bytes, ChunkBytes::Zero) {
+            ChunkBytes::Zero => return Bytes::new(),
+            ChunkBytes::One(bytes) => bytes,
+            ChunkBytes::Many(buffers) => buffers.into_bytes(),
+        };
+        if let Some(win) = self.window.as_ref() {
+            Self::add_capacity(win, &self.stats, bytes.len());
+        }
+        bytes
+    }
+
+    /// Splits off the first `n` bytes of the chunk, leaving the remainder in `self`.
+    ///
+    /// Each part returns its own bytes to the window as it is consumed or dropped, so
+    /// together they return exactly the capacity the original chunk held.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `n` exceeds the chunk's length.
+    pub fn split_to(&mut self, n: usize) -> Chunk {
+        if n == 0 {
+            return empty();
+        }
+
+        let bytes = match self.bytes {
+            ChunkBytes::Zero => panic!("split exceeds chunk size"),
+
+            ChunkBytes::One(ref mut bytes) => {
+                if bytes.len() < n {
+                    panic!("split exceeds chunk size");
+                }
+                ChunkBytes::One(bytes.split_to(n))
+            }
+
+            ChunkBytes::Many(ref mut buffers) => {
+                if buffers.len() < n {
+                    panic!("split exceeds chunk size");
+                }
+                let mut front = buffers.split_to(n);
+                if front.segment_count() == 1 {
+                    ChunkBytes::One(front.pop_front().unwrap())
+                } else {
+                    ChunkBytes::Many(front)
+                }
+            }
+        };
+
+        let chunk = Chunk {
+            bytes,
+            window: self.window.clone(),
+            stats: None,
+        };
+        track(chunk, &self.stats)
+    }
+
+    /// Up to `n` leading bytes of the chunk, without consuming them.
+    ///
+    /// The bytes are borrowed if they lie within the chunk's first segment; otherwise,
+    /// they are copied across segments.
+    pub fn peek<'a>(&'a self, n: usize) -> Cow<'a, [u8]> {
+        match self.bytes {
+            ChunkBytes::Zero => Cow::Borrowed(&[]),
+            ChunkBytes::One(ref bytes) => Cow::Borrowed(&bytes[..cmp::min(n, bytes.len())]),
+            ChunkBytes::Many(ref buffers) => {
+                let n = cmp::min(n, buffers.len());
+                match buffers.front() {
+                    Some(front) if n <= front.len() => Cow::Borrowed(&front[..n]),
+                    _ => {
+                        let bytes = buffers.iter().flat_map(|b| b.iter()).take(n);
+                        Cow::Owned(bytes.cloned().collect())
+                    }
+                }
+            }
+        }
+    }
+
+    /// The number of contiguous segments remaining in the chunk.
+    ///
+    /// A chunk with a single segment may be written from `bytes()` directly, while one
+    /// with several is better suited to a vectored write.
+    pub fn segment_count(&self) -> usize {
+        match self.bytes {
+            ChunkBytes::Zero => 0,
+            ChunkBytes::One(ref bytes) if bytes.is_empty() => 0,
+            ChunkBytes::One(_) => 1,
+            ChunkBytes::Many(ref buffers) => buffers.segment_count(),
+        }
+    }
+
+    /// The chunk's non-empty segments, in order.
+    fn segments(&self) -> impl Iterator<Item = &Bytes> {
+        let (one, many) = match self.bytes {
+            ChunkBytes::Zero => (None, None),
+            ChunkBytes::One(ref bytes) => (Some(bytes), None),
+            ChunkBytes::Many(ref buffers) => (None, Some(buffers)),
+        };
+        one.into_iter()
+            .chain(many.into_iter().flat_map(|b| b.iter()))
+            .filter(|b| !b.is_empty())
+    }
+
+    /// Converts the chunk into a stream of its segments.
+    ///
+    /// Each segment's capacity is returned to the window as the segment is yielded. If
+    /// the stream is dropped early, the remaining segments are returned as the chunk is
+    /// dropped. The stream never fails.
+    pub fn into_segment_stream(mut self) -> impl Stream<Item = Bytes, Error = Never> {
+        stream::poll_fn(move || Ok::<_, Never>(Async::Ready(self.pop_segment())))
+    }
+
+    fn pop_segment(&mut self) -> Option<Bytes> {
+        let bytes = match mem::replace(&mut self.bytes, ChunkBytes::Zero) {
+            ChunkBytes::Zero => return None,
+            ChunkBytes::One(bytes) => bytes,
+            ChunkBytes::Many(mut buffers) => {
+                let bytes = buffers.pop_front()?;
+                if !buffers.is_empty() {
+                    self.bytes = ChunkBytes::Many(buffers);
+                }
+                bytes
+            }
+        };
+
+        if let Some(win) = self.window.as_ref() {
+            Self::add_capacity(win, &self.stats, bytes.len());
+        }
+        Some(bytes)
+    }
+
+    fn add_capacity(wref: &WeakWindow, stats: &SharedStats, sz: usize) {
         if sz == 0 {
             return;
         }
+        if let Some(ref stats) = *stats {
+            stats.consumed(sz);
+        }
         // XXX this is probably a bit too heavyweight to do on every Buf::advance()?
         if let Some(ref wmut) = wref.upgrade() {
-            wmut.lock().expect("locking window").advertise_increment(sz);
+            wmut.lock().expect("locking window").return_claimed(sz);
         }
     }
 }
 
-// TODO this should be a Rope.
 #[derive(Debug)]
 enum ChunkBytes {
     Zero,
     One(Bytes),
-    Many {
-        remaining: usize,
-        buffers: VecDeque<Bytes>,
-    },
+    Many(Rope),
 }
 
 impl Drop for Chunk {
     /// When a chunk is dropped, all of its bytes are returned to the underlying window.
     fn drop(&mut self) {
         if let Some(win) = self.window.take() {
-            Self::add_capacity(&win, self.len());
+            Self::add_capacity(&win, &self.stats, self.len());
+        }
+        if let Some(stats) = self.stats.take() {
+            stats.chunk_dropped();
         }
         self.bytes = ChunkBytes::Zero;
     }
@@ -91,18 +270,14 @@ impl Drop for Chunk {
 
 impl Buf for Chunk {
     fn remaining(&self) -> usize {
-        match self.bytes {
-            ChunkBytes::Zero => 0,
-            ChunkBytes::One(ref bytes) => bytes.len(),
-            ChunkBytes::Many { ref remaining, .. } => *remaining,
-        }
+        self.len()
     }
 
     fn bytes(&self) -> &[u8] {
         match self.bytes {
             ChunkBytes::Zero => &[],
             ChunkBytes::One(ref bytes) => bytes.as_ref(),
-            ChunkBytes::Many { ref buffers, .. } => {
+            ChunkBytes::Many(ref buffers) => {
                 match buffers.front() {
                     None => &[],
                     Some(bytes) => bytes.as_ref(),
@@ -111,6 +286,17 @@ impl Buf for Chunk {
         }
     }
 
+    /// Fills `dst` with each of the chunk's segments, in order, so that a multi-segment
+    /// chunk may be written with a single vectored write.
+    fn bytes_vec<'a>(&'a self, dst: &mut [&'a IoVec]) -> usize {
+        let mut n = 0;
+        for (iov, segment) in dst.iter_mut().zip(self.segments()) {
+            *iov = segment.as_ref().into();
+            n += 1;
+        }
+        n
+    }
+
     fn advance(&mut self, sz: usize) {
         if sz == 0 {
             return;
@@ -126,54 +312,23 @@ impl Buf for Chunk {
                 if len < sz {
                     panic!("advance exceeds chunk size");
                 } else if len == sz {
-                    drop(bytes);
+                    bytes.clear();
                 } else {
                     drop(bytes.split_to(sz))
                 };
-                if let Some(ref win) = self.window.as_ref() {
-                    Self::add_capacity(win, sz);
+                if let Some(win) = self.window.as_ref() {
+                    Self::add_capacity(win, &self.stats, sz);
                 }
-                return;
             }
 
-            ChunkBytes::Many {
-                ref mut buffers,
-                ref mut remaining,
-            } => {
-                if *remaining < sz {
+            ChunkBytes::Many(ref mut buffers) => {
+                if buffers.len() < sz {
                     panic!("advance exceeds chunk size");
                 }
-                let orig_sz = sz;
-                let mut sz = sz;
-
-                while let Some(mut bytes) = buffers.pop_front() {
-                    let len = bytes.len();
-                    if sz < len {
-                        // Consume the beginning of the buffer.
-                        let rest = bytes.split_to(sz);
-                        drop(bytes);
-                        buffers.push_front(rest);
-
-                        // Commit the change
-                        *remaining -= orig_sz;
-                        if let Some(ref win) = self.window.as_ref() {
-                            Self::add_capacity(win, orig_sz);
-                        }
-                        return;
-                    }
-
-                    // Consume the entire buffer.
-                    drop(bytes);
-                    sz -= len;
-                    if sz == 0 {
-                        *remaining -= orig_sz;
-                        if let Some(ref win) = self.window.as_ref() {
-                            Self::add_capacity(win, orig_sz);
-                        }
-                        return;
-                    }
+                buffers.advance(sz);
+                if let Some(win) = self.window.as_ref() {
+                    Self::add_capacity(win, &self.stats, sz);
                 }
-                panic!("advance exceeds chunk size");
             }
         }
     }
@@ -0,0 +1,41 @@
+use futures::*;
+
+use super::{ByteReceiver, Chunk};
+#[cfg(feature = "futures03")]
+use super::compat::NotifyCache;
+
+pub fn new<E>(receiver: ByteReceiver<E>, max_sz: usize) -> ChunkStream<E> {
+    ChunkStream {
+        receiver,
+        max_sz,
+        #[cfg(feature = "futures03")]
+        notify: NotifyCache::default(),
+    }
+}
+
+/// A `Stream` of chunks read from a channel.
+///
+/// Created by `ByteReceiver::into_chunks`.
+#[derive(Debug)]
+pub struct ChunkStream<E> {
+    receiver: ByteReceiver<E>,
+    max_sz: usize,
+    #[cfg(feature = "futures03")]
+    pub(super) notify: NotifyCache,
+}
+
+impl<E> ChunkStream<E> {
+    /// Changes the maximum size of subsequent chunks.
+    pub fn set_max_chunk(&mut self, max_sz: usize) {
+        self.max_sz = max_sz;
+    }
+}
+
+impl<E> Stream for ChunkStream<E> {
+    type Item = Chunk;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, E> {
+        self.receiver.poll_chunk(self.max_sz)
+    }
+}
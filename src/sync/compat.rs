@@ -0,0 +1,96 @@
+//! Implements futures 0.3 `Stream` for the channel's handles.
+//!
+//! The channel parks and notifies futures 0.1 tasks. Each 0.3 poll therefore runs the
+//! corresponding 0.1 poll within a task that wakes the caller's `Waker` when notified, so
+//! that the window and buffer logic is shared by both task systems.
+
+use futures::Async;
+use futures::executor::{self, Notify, NotifyHandle};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use super::{ByteReceiver, Chunk, ChunkStream, WindowAdvertiser};
+
+/// Wakes a futures 0.3 task when a futures 0.1 task is notified.
+#[derive(Debug)]
+struct WakerNotify(Waker);
+
+impl Notify for WakerNotify {
+    fn notify(&self, _id: usize) {
+        self.0.wake_by_ref();
+    }
+}
+
+/// Holds the notifier built for a handle's last 0.3 poll, so that it is only rebuilt
+/// when the handle is polled by a different task.
+#[derive(Debug, Default)]
+pub struct NotifyCache(Option<Arc<WakerNotify>>);
+
+impl NotifyCache {
+    fn handle(&mut self, waker: &Waker) -> NotifyHandle {
+        let notify = match self.0 {
+            Some(ref notify) if notify.0.will_wake(waker) => notify.clone(),
+            _ => {
+                let notify = Arc::new(WakerNotify(waker.clone()));
+                self.0 = Some(notify.clone());
+                notify
+            }
+        };
+        NotifyHandle::from(notify)
+    }
+}
+
+/// Runs a futures 0.1 poll of `obj` such that the task it saves is notified by `notify`.
+fn poll_01<T, R, E, F>(obj: &mut T, notify: &NotifyHandle, f: F) -> Poll<Option<Result<R, E>>>
+where
+    F: FnOnce(&mut T) -> futures::Poll<Option<R>, E>,
+{
+    match executor::spawn(obj).poll_fn_notify(notify, 0, |obj| f(obj)) {
+        Ok(Async::Ready(Some(item))) => Poll::Ready(Some(Ok(item))),
+        Ok(Async::Ready(None)) => Poll::Ready(None),
+        Ok(Async::NotReady) => Poll::Pending,
+        Err(e) => Poll::Ready(Some(Err(e))),
+    }
+}
+
+/// Yields every byte buffered when polled as a single chunk, as `poll_all` does.
+///
+/// Unlike `poll_chunk`, chunks are bounded only by the window, and the receiver's chunk
+/// alignment and segment limit are ignored. Use `into_chunks` for a stream of chunks read
+/// as `poll_chunk` reads them.
+impl<E> Stream for ByteReceiver<E> {
+    type Item = Result<Chunk, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let rx = self.get_mut();
+        let notify = rx.notify.handle(cx.waker());
+        poll_01(rx, &notify, |rx| rx.poll_all())
+    }
+}
+
+impl<E> Stream for ChunkStream<E> {
+    type Item = Result<Chunk, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let chunks = self.get_mut();
+        let notify = chunks.notify.handle(cx.waker());
+        poll_01(chunks, &notify, futures::Stream::poll)
+    }
+}
+
+/// Yields window increments, ending once the receiver has been dropped.
+impl Stream for WindowAdvertiser {
+    type Item = usize;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<usize>> {
+        let wx = self.get_mut();
+        let notify = wx.notify.handle(cx.waker());
+        match poll_01(wx, &notify, futures::Stream::poll) {
+            Poll::Ready(Some(Ok(incr))) => Poll::Ready(Some(incr)),
+            Poll::Ready(Some(Err(()))) | Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use futures::*;
+
+use super::{ByteReceiver, Chunk, WindowAdvertiser};
+
+/// Runs a channel to completion, feeding each chunk read from `receiver` to `sink`.
+///
+/// The advertiser is polled alongside the receiver so that credit is returned as chunks
+/// are consumed; its increments are discarded. The returned future completes once the
+/// receiver has reached the end of the stream, i.e. once the senders have closed or been
+/// dropped. The receiver is then dropped, which closes the window, so the advertiser ends
+/// after publishing any remaining credit, even if `sink` still holds chunks.
+pub fn drive<E, F>(
+    mut advertiser: WindowAdvertiser,
+    receiver: ByteReceiver<E>,
+    mut sink: F,
+) -> impl Future<Item = (), Error = E>
+where
+    F: FnMut(Chunk),
+{
+    let mut receiver = Some(receiver);
+    let mut advertising = true;
+    future::poll_fn(move || {
+        // Read before advertising so that credit returned by consumed chunks is
+        // published in the same poll.
+        while let Some(mut rx) = receiver.take() {
+            match rx.poll_chunk(usize::MAX)? {
+                Async::Ready(Some(chunk)) => {
+                    sink(chunk);
+                    receiver = Some(rx);
+                }
+                // The receiver is dropped so that the window closes and the advertiser
+                // ends.
+                Async::Ready(None) => {}
+                Async::NotReady => {
+                    receiver = Some(rx);
+                    break;
+                }
+            }
+        }
+
+        while advertising {
+            match advertiser.poll() {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(Async::Ready(None)) | Err(()) => advertising = false,
+                Ok(Async::NotReady) => break,
+            }
+        }
+
+        if receiver.is_none() && !advertising {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    })
+}
@@ -0,0 +1,161 @@
+//! Lets a receiver read a channel whose error type differs from its own, as returned by
+//! `ByteReceiver::erase_error`.
+//!
+//! The sender's errors are boxed as the receiver locks the buffer, and unboxed again as
+//! it unlocks, so the sender and any other receiver handles are unaffected.
+
+use std::error::Error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, LockResult, MutexGuard, PoisonError};
+
+use buffer::ChannelBuffer;
+use super::SharedBuffer;
+
+/// A boxed error, as read by an erased receiver.
+pub type BoxError = Box<dyn Error + Send>;
+
+/// A receiver's handle on its channel's buffer.
+pub enum ReceiverBuffer<E> {
+    /// The channel's buffer, which holds errors of the receiver's type.
+    Shared(SharedBuffer<E>),
+
+    /// The channel's buffer, whose errors are converted to the receiver's type.
+    Erased(Arc<dyn ErasedBuffer<E> + Send + Sync>),
+}
+
+impl<E> ReceiverBuffer<E> {
+    pub fn lock(&self) -> LockResult<BufferGuard<'_, E>> {
+        match *self {
+            ReceiverBuffer::Shared(ref buffer) => match buffer.lock() {
+                Ok(guard) => Ok(BufferGuard::Shared(guard)),
+                Err(e) => Err(PoisonError::new(BufferGuard::Shared(e.into_inner()))),
+            },
+            ReceiverBuffer::Erased(ref buffer) => buffer.lock(),
+        }
+    }
+
+    /// Identifies the channel, so that every handle on it has the same key.
+    pub fn key(&self) -> usize {
+        match *self {
+            ReceiverBuffer::Shared(ref buffer) => &**buffer as *const _ as usize,
+            ReceiverBuffer::Erased(ref buffer) => buffer.key(),
+        }
+    }
+}
+
+impl<E> Clone for ReceiverBuffer<E> {
+    fn clone(&self) -> Self {
+        match *self {
+            ReceiverBuffer::Shared(ref buffer) => ReceiverBuffer::Shared(buffer.clone()),
+            ReceiverBuffer::Erased(ref buffer) => ReceiverBuffer::Erased(buffer.clone()),
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for ReceiverBuffer<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReceiverBuffer::Shared(ref buffer) => buffer.fmt(f),
+            ReceiverBuffer::Erased(_) => f.write_str("ErasedBuffer"),
+        }
+    }
+}
+
+/// A locked `ReceiverBuffer`.
+pub enum BufferGuard<'a, E> {
+    Shared(MutexGuard<'a, Option<ChannelBuffer<E>>>),
+    Erased(Box<dyn DerefMut<Target = Option<ChannelBuffer<E>>> + 'a>),
+}
+
+impl<'a, E> Deref for BufferGuard<'a, E> {
+    type Target = Option<ChannelBuffer<E>>;
+
+    fn deref(&self) -> &Self::Target {
+        match *self {
+            BufferGuard::Shared(ref guard) => guard,
+            BufferGuard::Erased(ref guard) => guard,
+        }
+    }
+}
+
+impl<'a, E> DerefMut for BufferGuard<'a, E> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match *self {
+            BufferGuard::Shared(ref mut guard) => guard,
+            BufferGuard::Erased(ref mut guard) => guard,
+        }
+    }
+}
+
+/// A channel buffer that is read with a different error type than it holds.
+pub trait ErasedBuffer<E> {
+    fn lock(&self) -> LockResult<BufferGuard<'_, E>>;
+
+    fn key(&self) -> usize;
+}
+
+/// Boxes the errors of the wrapped buffer.
+pub struct BoxErrors<E>(pub ReceiverBuffer<E>);
+
+impl<E: Error + Send + 'static> ErasedBuffer<BoxError> for BoxErrors<E> {
+    fn lock(&self) -> LockResult<BufferGuard<'_, BoxError>> {
+        let (guard, poisoned) = match self.0.lock() {
+            Ok(guard) => (guard, false),
+            Err(e) => (e.into_inner(), true),
+        };
+        let guard = BufferGuard::Erased(Box::new(BoxedGuard::new(guard)));
+        if poisoned {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    fn key(&self) -> usize {
+        self.0.key()
+    }
+}
+
+/// Holds a locked buffer's state with its errors boxed, and restores it on drop.
+struct BoxedGuard<'a, E: Error + 'static> {
+    guard: BufferGuard<'a, E>,
+    boxed: Option<ChannelBuffer<BoxError>>,
+}
+
+impl<'a, E: Error + Send + 'static> BoxedGuard<'a, E> {
+    fn new(mut guard: BufferGuard<'a, E>) -> Self {
+        let boxed = guard.take().map(|b| b.map_err(|e| Box::new(e) as BoxError));
+        BoxedGuard { guard, boxed }
+    }
+}
+
+impl<'a, E: Error + 'static> Deref for BoxedGuard<'a, E> {
+    type Target = Option<ChannelBuffer<BoxError>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.boxed
+    }
+}
+
+impl<'a, E: Error + 'static> DerefMut for BoxedGuard<'a, E> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.boxed
+    }
+}
+
+impl<'a, E: Error + 'static> Drop for BoxedGuard<'a, E> {
+    fn drop(&mut self) {
+        *self.guard = self.boxed.take().map(|buffer| match buffer {
+            // The receiver may cancel with any error, but the sender may only take one of
+            // its own type.
+            ChannelBuffer::ReceiverCancelled(e) => {
+                ChannelBuffer::ReceiverCancelled(e.and_then(|e| e.downcast().ok().map(|e| *e)))
+            }
+            ChannelBuffer::ReceiverFailed(e) => {
+                ChannelBuffer::ReceiverFailed(e.and_then(|e| e.downcast().ok().map(|e| *e)))
+            }
+            buffer => buffer.map_err(|e| *e.downcast().expect("erased error has the channel's type")),
+        });
+    }
+}
@@ -0,0 +1,88 @@
+use bytes::{Buf, Bytes};
+use futures::*;
+
+use super::{ByteReceiver, Chunk};
+use super::chunk;
+
+pub fn new<E, S>(receiver: ByteReceiver<E>, sink: S) -> ForwardInto<E, S> {
+    ForwardInto {
+        receiver: Some(receiver),
+        sink: Some(sink),
+        chunk: None,
+    }
+}
+
+/// Forwards a channel's data into a `Sink`, completing with the sink once the channel
+/// has ended and all data has been flushed.
+///
+/// Created by `ByteReceiver::forward_into`.
+#[derive(Debug)]
+pub struct ForwardInto<E, S> {
+    receiver: Option<ByteReceiver<E>>,
+    sink: Option<S>,
+
+    /// A chunk whose segments have not all been accepted by the sink.
+    chunk: Option<Chunk>,
+}
+
+impl<E, S> ForwardInto<E, S>
+where
+    S: Sink<SinkItem = Bytes>,
+    E: From<S::SinkError>,
+{
+    /// Sends the buffered chunk's segments, returning the capacity of each segment to
+    /// the window as the sink accepts it.
+    fn send_chunk(&mut self) -> Poll<(), E> {
+        let sink = self.sink.as_mut().expect("polled after completion");
+        if let Some(mut chunk) = self.chunk.take() {
+            while let Some(segment) = chunk::front_segment(&chunk) {
+                let sz = segment.len();
+                if let AsyncSink::NotReady(_) = sink.start_send(segment)? {
+                    self.chunk = Some(chunk);
+                    return Ok(Async::NotReady);
+                }
+                chunk.advance(sz);
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<E, S> Future for ForwardInto<E, S>
+where
+    S: Sink<SinkItem = Bytes>,
+    E: From<S::SinkError>,
+{
+    type Item = S;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<S, E> {
+        loop {
+            if self.send_chunk()?.is_not_ready() {
+                // The sink may be able to accept more once it has flushed.
+                let sink = self.sink.as_mut().expect("polled after completion");
+                try_ready!(sink.poll_complete());
+                continue;
+            }
+
+            let polled = match self.receiver.as_mut() {
+                Some(rx) => rx.poll_chunk(usize::MAX)?,
+                None => Async::Ready(None),
+            };
+            match polled {
+                Async::Ready(Some(chunk)) => self.chunk = Some(chunk),
+                Async::Ready(None) => {
+                    self.receiver = None;
+                    let sink = self.sink.as_mut().expect("polled after completion");
+                    try_ready!(sink.poll_complete());
+                    return Ok(Async::Ready(self.sink.take().unwrap()));
+                }
+                Async::NotReady => {
+                    let sink = self.sink.as_mut().expect("polled after completion");
+                    try_ready!(sink.poll_complete());
+                    return Ok(Async::NotReady);
+                }
+            }
+        }
+    }
+}
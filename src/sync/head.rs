@@ -0,0 +1,135 @@
+use futures::*;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{ChannelBuffer, SharedWindow};
+use super::chunk;
+use super::erased::ReceiverBuffer;
+use super::receiver::{self, PollChunk};
+use super::stats::SharedStats;
+
+pub fn new<E>(
+    buffer: ReceiverBuffer<E>,
+    window: SharedWindow,
+    stats: SharedStats,
+    receivers: Arc<AtomicUsize>,
+    len: usize,
+) -> (HeadReceiver<E>, SharedGate) {
+    let gate = Arc::new(Mutex::new(Gate {
+        remaining: len,
+        released: len == 0,
+        awaiting_body: None,
+    }));
+    let head = HeadReceiver {
+        buffer,
+        window,
+        stats,
+        receivers,
+        gate: gate.clone(),
+    };
+    (head, gate)
+}
+
+pub type SharedGate = Arc<Mutex<Gate>>;
+
+/// Holds back a body receiver until its head has been read.
+#[derive(Debug)]
+pub struct Gate {
+    remaining: usize,
+    /// Set once the head has stopped reading, even if it ended early.
+    released: bool,
+    awaiting_body: Option<task::Task>,
+}
+
+impl Gate {
+    fn release(&mut self) {
+        self.released = true;
+        if let Some(t) = self.awaiting_body.take() {
+            t.notify();
+        }
+    }
+}
+
+/// Indicates whether the head has been fully read. If it hasn't and `park` is set, the
+/// current task is saved to be notified when it has.
+pub fn poll_released(gate: &SharedGate, park: bool) -> Async<()> {
+    let mut gate = gate.lock().expect("locking byte channel head");
+    if gate.released {
+        return Async::Ready(());
+    }
+    if park {
+        gate.awaiting_body = Some(task::current());
+    }
+    Async::NotReady
+}
+
+/// Reads a fixed number of bytes from the front of a channel.
+///
+/// Created by `ByteReceiver::split_at_offset`.
+#[derive(Debug)]
+pub struct HeadReceiver<E> {
+    buffer: ReceiverBuffer<E>,
+    window: SharedWindow,
+    stats: SharedStats,
+    receivers: Arc<AtomicUsize>,
+    gate: SharedGate,
+}
+
+impl<E> HeadReceiver<E> {
+    /// The number of head bytes that have not yet been read.
+    pub fn remaining(&self) -> usize {
+        self.gate.lock().expect("locking byte channel head").remaining
+    }
+
+    /// Poll at most `max_sz` bytes from the head of the channel.
+    ///
+    /// Once all head bytes have been read, `None` is returned. If the sender fails the
+    /// stream before then, the head ends early, as `remaining` reports, and the error is
+    /// left for the body to read.
+    pub fn poll_chunk(&mut self, max_sz: usize) -> PollChunk<E> {
+        let _guard = receiver::enter_poll(&self.buffer);
+        let mut gate = self.gate.lock().expect("locking byte channel head");
+        if gate.released {
+            return Ok(Async::Ready(None));
+        }
+
+        let sz = cmp::min(max_sz, gate.remaining);
+        match receiver::poll_buffer(&self.buffer, &self.window, sz) {
+            Ok(Async::Ready(Some(chunk))) => {
+                gate.remaining -= chunk.len();
+                if gate.remaining == 0 {
+                    gate.release();
+                }
+                Ok(Async::Ready(Some(chunk::track(chunk, &self.stats))))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => {
+                // Restore the failure so that the body does not observe a clean end.
+                let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+                if buffer.is_none() {
+                    *buffer = Some(ChannelBuffer::SenderFailed(e));
+                }
+                gate.release();
+                Ok(Async::Ready(None))
+            }
+            res => {
+                // The channel ended before the head was read, so there's nothing for the
+                // body to wait for.
+                gate.release();
+                res
+            }
+        }
+    }
+}
+
+impl<E> Drop for HeadReceiver<E> {
+    fn drop(&mut self) {
+        if let Ok(mut gate) = self.gate.lock() {
+            gate.release();
+        }
+        if self.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            receiver::lose_receiver(&self.buffer, &self.window);
+        }
+    }
+}
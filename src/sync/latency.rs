@@ -0,0 +1,40 @@
+use metrics::histogram;
+use std::time::Instant;
+
+/// Records how long a task stays parked, e.g. waiting for data or for window.
+#[derive(Clone, Debug)]
+pub struct ParkTimer {
+    name: &'static str,
+    label: &'static str,
+    parked_at: Option<Instant>,
+}
+
+impl ParkTimer {
+    pub fn new(name: &'static str, label: &'static str) -> ParkTimer {
+        ParkTimer {
+            name,
+            label,
+            parked_at: None,
+        }
+    }
+
+    /// A timer recording into the same histogram for another task, e.g. a cloned handle's.
+    pub fn sibling(&self) -> ParkTimer {
+        ParkTimer::new(self.name, self.label)
+    }
+
+    /// Notes that the task has parked. Repeated polls while parked do not restart the
+    /// timer.
+    pub fn park(&mut self) {
+        if self.parked_at.is_none() {
+            self.parked_at = Some(Instant::now());
+        }
+    }
+
+    /// Records the time parked, if the task had parked.
+    pub fn wake(&mut self) {
+        if let Some(t0) = self.parked_at.take() {
+            histogram!(self.name, "channel" => self.label).record(t0.elapsed());
+        }
+    }
+}
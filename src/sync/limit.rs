@@ -0,0 +1,70 @@
+use futures::*;
+use std::cmp;
+
+use LimitExceeded;
+use super::ByteReceiver;
+use super::receiver::PollChunk;
+
+pub fn new<E>(receiver: ByteReceiver<E>, max_total: u64) -> LimitReceiver<E> {
+    LimitReceiver {
+        receiver,
+        remaining: max_total,
+        exceeded: false,
+    }
+}
+
+/// Reads at most a fixed number of bytes from a channel, failing if the channel carries
+/// more.
+///
+/// Created by `ByteReceiver::limit`.
+#[derive(Debug)]
+pub struct LimitReceiver<E> {
+    receiver: ByteReceiver<E>,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl<E: From<LimitExceeded>> LimitReceiver<E> {
+    /// The number of bytes that may still be read.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Poll at most `max_sz` bytes from the channel.
+    ///
+    /// Chunks are cut short at the limit. Once the limit has been reached, the next poll
+    /// fails with `LimitExceeded` if the channel has more data, as do all subsequent
+    /// polls; if the channel ends at the limit, the read completes normally.
+    pub fn poll_chunk(&mut self, max_sz: usize) -> PollChunk<E> {
+        if self.exceeded {
+            return Err(LimitExceeded.into());
+        }
+
+        if self.remaining == 0 {
+            // Read a single byte to determine whether the channel has ended.
+            return match try_ready!(self.receiver.poll_chunk(1)) {
+                Some(_) => {
+                    self.exceeded = true;
+                    Err(LimitExceeded.into())
+                }
+                None => Ok(Async::Ready(None)),
+            };
+        }
+
+        let max_sz = cmp::min(max_sz as u64, self.remaining) as usize;
+        let chunk = try_ready!(self.receiver.poll_chunk(max_sz));
+        if let Some(ref c) = chunk {
+            self.remaining -= c.len() as u64;
+        }
+        Ok(Async::Ready(chunk))
+    }
+}
+
+impl<E: From<LimitExceeded>> Stream for LimitReceiver<E> {
+    type Item = super::Chunk;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<Option<super::Chunk>, E> {
+        self.poll_chunk(usize::MAX)
+    }
+}
@@ -0,0 +1,169 @@
+use bytes::Bytes;
+use futures::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use PushError;
+use super::{ByteReceiver, ByteSender};
+use super::receiver::PollChunk;
+
+pub fn new<E, M>(tx: ByteSender<E>, rx: ByteReceiver<E>) -> (MetaSender<E, M>, MetaReceiver<E, M>) {
+    let state = Arc::new(Mutex::new(MetaState {
+        consumed: 0,
+        pending: VecDeque::new(),
+        awaiting_meta: None,
+        lost_receiver: false,
+    }));
+    let tx = MetaSender {
+        sender: tx,
+        state: state.clone(),
+    };
+    let rx = MetaReceiver {
+        receiver: rx,
+        state,
+        ended: false,
+    };
+    (tx, rx)
+}
+
+type SharedState<M> = Arc<Mutex<MetaState<M>>>;
+
+/// Orders metadata relative to the channel's data.
+///
+/// Each item is recorded with the number of bytes pushed before it, so that the receiver
+/// stops reading data at that offset until the item has been taken.
+#[derive(Debug)]
+struct MetaState<M> {
+    /// The number of bytes the receiver has read from the channel.
+    consumed: usize,
+    pending: VecDeque<(usize, M)>,
+    awaiting_meta: Option<task::Task>,
+    lost_receiver: bool,
+}
+
+/// Pushes bytes and out-of-band metadata into a channel.
+///
+/// Created by `sync::with_meta`.
+#[derive(Debug)]
+pub struct MetaSender<E, M> {
+    sender: ByteSender<E>,
+    state: SharedState<M>,
+}
+
+/// Reads bytes and out-of-band metadata from a channel, in the order they were pushed.
+///
+/// Created by `sync::with_meta`.
+#[derive(Debug)]
+pub struct MetaReceiver<E, M> {
+    receiver: ByteReceiver<E>,
+    state: SharedState<M>,
+    ended: bool,
+}
+
+impl<E, M> Clone for MetaSender<E, M> {
+    fn clone(&self) -> Self {
+        MetaSender {
+            sender: self.sender.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<E, M> MetaSender<E, M> {
+    /// The underlying sender, e.g. for pushes other than `push_bytes`.
+    ///
+    /// Bytes pushed through the underlying sender are ordered with metadata like any
+    /// other.
+    pub fn sender(&mut self) -> &mut ByteSender<E> {
+        &mut self.sender
+    }
+
+    pub fn available_window(&self) -> usize {
+        self.sender.available_window()
+    }
+
+    pub fn push_bytes(&mut self, bytes: Bytes) -> Result<usize, PushError> {
+        self.sender.push_bytes(bytes)
+    }
+
+    /// Pushes metadata to be received after all of the bytes pushed so far.
+    ///
+    /// Metadata does not use the channel's window.
+    pub fn push_meta(&mut self, meta: M) -> Result<(), PushError> {
+        let mut state = self.state.lock().expect("locking byte channel metadata");
+        if state.lost_receiver {
+            return Err(PushError::LostReceiver);
+        }
+
+        // The state is locked while the buffer's length is read so that the receiver
+        // can't consume data in between.
+        let offset = state.consumed + self.sender.len();
+        state.pending.push_back((offset, meta));
+        if let Some(t) = state.awaiting_meta.take() {
+            t.notify();
+        }
+        Ok(())
+    }
+
+    pub fn close(self) {
+        self.sender.close()
+    }
+}
+
+impl<E, M> MetaReceiver<E, M> {
+    /// Poll at most `max_sz` bytes from the channel.
+    ///
+    /// Reads stop at metadata boundaries: if metadata was pushed before the next byte,
+    /// an empty chunk is returned and the metadata must be taken with `poll_meta` before
+    /// more data can be read. If no data is available, the current task is notified when
+    /// either data or metadata is pushed.
+    pub fn poll_chunk(&mut self, max_sz: usize) -> PollChunk<E> {
+        let mut state = self.state.lock().expect("locking byte channel metadata");
+        let max_sz = match state.pending.front() {
+            Some(&(offset, _)) => ::std::cmp::min(max_sz, offset - state.consumed),
+            None => max_sz,
+        };
+
+        let poll = self.receiver.poll_chunk(max_sz);
+        match poll {
+            Ok(Async::Ready(Some(ref chunk))) => state.consumed += chunk.len(),
+            Ok(Async::NotReady) => state.awaiting_meta = Some(task::current()),
+            _ => self.ended = true,
+        }
+        poll
+    }
+
+    /// Takes the next metadata if no data was pushed before it.
+    ///
+    /// Returns `NotReady` if data must be read with `poll_chunk` first. If no metadata
+    /// is pending, the current task is notified once metadata or data is pushed or the
+    /// channel ends. Once the channel has ended and all metadata has been taken, returns
+    /// `None`.
+    pub fn poll_meta(&mut self) -> Async<Option<M>> {
+        let mut state = self.state.lock().expect("locking byte channel metadata");
+        let ready = match state.pending.front() {
+            Some(&(offset, _)) => offset == state.consumed,
+            None => self.ended,
+        };
+        if ready {
+            return Async::Ready(state.pending.pop_front().map(|(_, m)| m));
+        }
+
+        if state.pending.is_empty() {
+            // Data and the end of the stream are read with `poll_chunk`, so either one
+            // wakes the task as well as metadata.
+            state.awaiting_meta = Some(task::current());
+            let _ = self.receiver.poll_readable();
+        }
+        Async::NotReady
+    }
+}
+
+impl<E, M> Drop for MetaReceiver<E, M> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.lost_receiver = true;
+            state.pending.clear();
+        }
+    }
+}
@@ -1,31 +1,124 @@
+use futures::{Stream, stream};
 use std::sync::{Arc, Mutex, Weak};
 
+use LimitExceeded;
 use buffer::ChannelBuffer;
 use window::Window;
 
+mod async_read;
+mod async_write;
+pub mod broadcast;
+mod builder;
 mod chunk;
+mod chunks;
+#[cfg(feature = "futures03")]
+mod compat;
+mod drive;
+mod erased;
+mod forward;
+mod head;
+mod limit;
+mod meta;
+mod observer;
+#[cfg(feature = "metrics")]
+mod latency;
+#[cfg(feature = "timer")]
+mod paced;
+mod reader;
 mod receiver;
+#[cfg(feature = "record")]
+mod record;
 mod sender;
+mod stats;
+#[cfg(feature = "timer")]
+mod timeout;
+mod watchdog;
 mod window;
 
+pub use self::async_read::AsyncReader;
+pub use self::async_write::AsyncWriter;
+pub use self::builder::{Builder, PushAfterClose};
 pub use self::chunk::Chunk;
+pub use self::chunks::ChunkStream;
+pub use self::drive::drive;
+pub use self::forward::ForwardInto;
+pub use self::head::HeadReceiver;
+pub use self::limit::LimitReceiver;
+pub use self::meta::{MetaReceiver, MetaSender};
+pub use self::observer::WindowObserver;
+#[cfg(feature = "timer")]
+pub use self::paced::{Paced, Timer};
+pub use self::reader::from_reader;
 pub use self::sender::ByteSender;
-pub use self::receiver::ByteReceiver;
+pub use self::stats::{ByteChannelStats, ChannelStats};
+pub use self::receiver::{ByteReceiver, ChunkOrEnd, PollNoPark, TakeAllError, TryRecvError};
+#[cfg(feature = "record")]
+pub use self::record::replay;
+#[cfg(feature = "timer")]
+pub use self::timeout::RecvTimeout;
+pub use self::watchdog::Watchdog;
 pub use self::window::WindowAdvertiser;
 
 /// Creates an asynchronous channel for transfering a stream of immutable `Bytes`.
 ///
 /// A sender must be aware of the receiver's available window size and take care not to
 pub fn new<E>(initial_window_size: usize) -> (WindowAdvertiser, ByteSender<E>, ByteReceiver<E>) {
-    let buffer = Arc::new(Mutex::new(Some(ChannelBuffer::default())));
-    let window = Arc::new(Mutex::new(Window::new(initial_window_size)));
+    Builder::new().window_size(initial_window_size).build()
+}
+
+/// Creates a byte channel that carries at most `total_limit` bytes over its lifetime,
+/// e.g. to bound the size of a transfer.
+///
+/// Pushes that fit within the limit are accepted as by `new`, and the sender's available
+/// window never exceeds the bytes remaining under the limit. A push that would exceed the
+/// limit fails with `PushError::LimitExceeded`, and the receiver fails with
+/// `LimitExceeded` after reading the bytes pushed before it.
+pub fn new_with_limit<E>(
+    initial_window_size: usize,
+    total_limit: usize,
+) -> (WindowAdvertiser, ByteSender<E>, ByteReceiver<E>)
+where
+    E: From<LimitExceeded>,
+{
+    let (wx, mut tx, rx) = Builder::new()
+        .window_size(initial_window_size)
+        .total_limit(total_limit)
+        .build();
+    tx.fail_on_total_limit(E::from);
+    (wx, tx, rx)
+}
+
+/// Creates a byte channel that buffers at most `capacity` bytes, including bytes held by
+/// unconsumed chunks.
+///
+/// Unlike `new`, the window is managed internally: capacity becomes available to the
+/// sender as soon as the receiver consumes chunks. Senders may wait for capacity with
+/// `ByteSender::poll_capacity`.
+pub fn bounded<E>(capacity: usize) -> (ByteSender<E>, ByteReceiver<E>) {
+    let (wx, tx, rx) = new(capacity);
+    wx.into_automatic();
+    (tx, rx)
+}
 
-    let wx = window::new(window.clone());
-    let tx = sender::new(buffer.clone(), window.clone());
-    let rx = receiver::new(buffer, window);
+/// Creates a byte channel that also carries out-of-band metadata.
+///
+/// Metadata is received in order relative to the channel's data: the receiver's reads
+/// stop at each item until it has been taken.
+pub fn with_meta<E, M>(
+    initial_window_size: usize,
+) -> (WindowAdvertiser, MetaSender<E, M>, MetaReceiver<E, M>) {
+    let (wx, tx, rx) = new(initial_window_size);
+    let (tx, rx) = meta::new(tx, rx);
     (wx, tx, rx)
 }
 
+/// Converts a receiver into a stream of chunks of at most `max_sz` bytes.
+///
+/// Each chunk opens the window as it is consumed or dropped, as with `poll_chunk`.
+pub fn stream_fn<E>(mut receiver: ByteReceiver<E>, max_sz: usize) -> impl Stream<Item = Chunk, Error = E> {
+    stream::poll_fn(move || receiver.poll_chunk(max_sz))
+}
+
 type SharedBuffer<E> = Arc<Mutex<Option<ChannelBuffer<E>>>>;
 type SharedWindow = Arc<Mutex<Window>>;
 type WeakWindow = Weak<Mutex<Window>>;
@@ -35,5 +128,5 @@ fn return_buffer_to_window<E>(buffer: &Option<ChannelBuffer<E>>, window: &Shared
     if sz == 0 {
         return;
     }
-    (*window.lock().expect("locking byte channel window")).advertise_increment(sz);
+    (*window.lock().expect("locking byte channel window")).return_claimed(sz);
 }
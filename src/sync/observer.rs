@@ -0,0 +1,107 @@
+use futures::task::AtomicTask;
+use std::mem;
+use std::sync::{Arc, Weak};
+
+use super::{SharedWindow, WeakWindow};
+use super::stats::{ByteChannelStats, SharedStats};
+
+pub fn new(window: &SharedWindow, stats: SharedStats) -> WindowObserver {
+    let observed = {
+        let mut window = window.lock().expect("locking byte channel window");
+        (*window).add_observer();
+        (*window).observed_task()
+    };
+    WindowObserver {
+        window: Arc::downgrade(window),
+        observed,
+        stats,
+    }
+}
+
+/// Observes a channel's flow-control state without participating in it.
+///
+/// An observer does not keep the channel alive: once the channel's handles and chunks
+/// have been dropped, its advertiser terminates as it otherwise would, and the observer
+/// reports an empty window.
+///
+/// Created by `ByteReceiver::window_observer`.
+#[derive(Debug)]
+pub struct WindowObserver {
+    window: WeakWindow,
+    /// Notified whenever this observer releases a reference to the window.
+    observed: Arc<AtomicTask>,
+    stats: SharedStats,
+}
+
+impl WindowObserver {
+    /// The window currently available to the channel's senders, as
+    /// `ByteSender::available_window` reports it.
+    pub fn available(&self) -> usize {
+        self.read(|w| w.available())
+    }
+
+    /// Capacity that has been returned by the receiver but not yet advertised.
+    pub fn pending(&self) -> usize {
+        self.read(|w| w.snapshot().pending)
+    }
+
+    /// The channel's activity counters, if it collects stats.
+    pub fn stats(&self) -> Option<ByteChannelStats> {
+        self.stats.as_ref().map(|s| s.snapshot())
+    }
+
+    /// Calls `f` with the window, unless the channel has been dropped.
+    ///
+    /// The window is briefly referenced while `f` runs, which an advertiser checking
+    /// whether the window is orphaned may observe. Once the reference is released, the
+    /// advertiser is notified so that it checks again.
+    pub(super) fn with_window<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&SharedWindow) -> R,
+    {
+        let window = self.window.upgrade()?;
+        let r = f(&window);
+        drop(window);
+        if self.window.strong_count() == 1 {
+            self.observed.notify();
+        }
+        Some(r)
+    }
+
+    fn read<F: FnOnce(&::window::Window) -> usize>(&self, f: F) -> usize {
+        self.with_window(|w| f(&w.lock().expect("locking byte channel window")))
+            .unwrap_or(0)
+    }
+}
+
+impl Clone for WindowObserver {
+    fn clone(&self) -> Self {
+        // The weak reference is taken before the observer is counted, so the window
+        // never appears to have fewer references than observers.
+        let window = self.window.clone();
+        self.with_window(|w| (*w.lock().expect("locking byte channel window")).add_observer());
+        WindowObserver {
+            window,
+            observed: self.observed.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl Drop for WindowObserver {
+    fn drop(&mut self) {
+        let weak = mem::replace(&mut self.window, Weak::new());
+        let window = match weak.upgrade() {
+            Some(w) => w,
+            None => return,
+        };
+        if let Ok(mut w) = window.lock() {
+            w.remove_observer();
+        }
+        // Release both references before the advertiser is notified, so that it
+        // observes an orphaned window.
+        drop(weak);
+        drop(window);
+        self.observed.notify();
+    }
+}
@@ -0,0 +1,97 @@
+use futures::*;
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use super::WindowAdvertiser;
+
+pub fn new<T: Timer>(advertiser: WindowAdvertiser, rate: usize, timer: T) -> Paced<T> {
+    assert!(0 < rate, "pacing rate must be non-zero");
+    let released_at = timer.now();
+    Paced {
+        advertiser,
+        rate: rate as u128,
+        timer,
+        released_at,
+        delay: None,
+    }
+}
+
+/// A source of time used to pace window advertisements.
+pub trait Timer {
+    type Delay: Future<Item = (), Error = ()>;
+
+    fn now(&self) -> Instant;
+
+    /// Returns a future that completes at `deadline`.
+    fn delay_until(&self, deadline: Instant) -> Self::Delay;
+}
+
+/// Publishes window increments at a bounded rate.
+///
+/// Credit accrues continuously at `rate` bytes per second, up to one second's worth, and
+/// is published as the receiver returns capacity to the window. Credit beyond the accrued
+/// amount stays pending in the window, so the sender cannot use it early.
+///
+/// Created by `WindowAdvertiser::paced`.
+#[derive(Debug)]
+pub struct Paced<T: Timer> {
+    advertiser: WindowAdvertiser,
+    rate: u128,
+    timer: T,
+    /// The time up to which accrued credit has been released.
+    released_at: Instant,
+    delay: Option<T::Delay>,
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+impl<T: Timer> Paced<T> {
+    /// The number of bytes that may be released at `now`.
+    fn accrued(&mut self, now: Instant) -> usize {
+        // Don't allow more than a second of credit to accrue while idle.
+        let max_idle = Duration::from_secs(1);
+        if max_idle < now.duration_since(self.released_at) {
+            self.released_at = now - max_idle;
+        }
+
+        let elapsed = now.duration_since(self.released_at);
+        (elapsed.as_nanos() * self.rate / NANOS_PER_SEC) as usize
+    }
+
+    /// The time it takes for `sz` bytes of credit to accrue.
+    fn duration_of(&self, sz: usize) -> Duration {
+        let nanos = (sz as u128 * NANOS_PER_SEC).div_ceil(self.rate);
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+impl<T: Timer> Stream for Paced<T> {
+    type Item = usize;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<usize>, ()> {
+        loop {
+            if let Some(mut delay) = self.delay.take() {
+                if delay.poll()?.is_not_ready() {
+                    self.delay = Some(delay);
+                    return Ok(Async::NotReady);
+                }
+            }
+
+            let now = self.timer.now();
+            let accrued = self.accrued(now);
+            if accrued == 0 {
+                // Wait for a byte of credit to accrue.
+                let deadline = self.released_at + self.duration_of(1);
+                self.delay = Some(self.timer.delay_until(cmp::max(now, deadline)));
+                continue;
+            }
+
+            let incr = try_ready!(self.advertiser.poll_max(accrued));
+            if let Some(sz) = incr {
+                self.released_at += self.duration_of(sz);
+            }
+            return Ok(Async::Ready(incr));
+        }
+    }
+}
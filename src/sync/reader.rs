@@ -0,0 +1,60 @@
+use bytes::Bytes;
+use futures::Stream;
+use std::cmp;
+use std::io::{self, Read};
+use std::thread;
+
+use super::{ByteReceiver, ByteSender, WindowAdvertiser};
+
+/// Creates a receiver that reads from a blocking `reader`.
+///
+/// A background thread reads at most `chunk_size` bytes at a time from `reader`, never
+/// exceeding the window, and pushes them into the channel. The thread blocks while the
+/// window is exhausted and exits once `reader` reaches EOF, `reader` fails, or the
+/// receiver is dropped. Read errors are delivered to the receiver.
+///
+/// ## Panics
+///
+/// Panics if `chunk_size` is zero.
+pub fn from_reader<R>(reader: R, window: usize, chunk_size: usize) -> ByteReceiver<io::Error>
+where
+    R: Read + Send + 'static,
+{
+    assert!(0 < chunk_size, "chunk size must be non-zero");
+    let (advertiser, tx, rx) = super::new(window);
+    thread::spawn(move || pump(reader, chunk_size, advertiser, tx));
+    rx
+}
+
+fn pump<R: Read>(
+    mut reader: R,
+    chunk_size: usize,
+    advertiser: WindowAdvertiser,
+    mut tx: ByteSender<io::Error>,
+) {
+    let mut increments = advertiser.wait();
+    loop {
+        let available = tx.available_window();
+        if available == 0 {
+            // Block until the receiver returns capacity. The advertiser ends once the
+            // receiver has been dropped.
+            match increments.next() {
+                Some(Ok(_)) => continue,
+                _ => return,
+            }
+        }
+
+        let mut buf = vec![0; cmp::min(available, chunk_size)];
+        match reader.read(&mut buf) {
+            Ok(0) => return tx.close(),
+            Ok(sz) => {
+                buf.truncate(sz);
+                if tx.push_bytes(Bytes::from(buf)).is_err() {
+                    return;
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return tx.reset(e),
+        }
+    }
+}
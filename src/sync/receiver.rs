@@ -1,144 +1,1236 @@
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
+#[cfg(feature = "checksum")]
+use crc32fast::Hasher;
 use futures::*;
-use std::collections::VecDeque;
+use std::cell::RefCell;
 use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "timer")]
+use std::time::Duration;
+use std::time::Instant;
 
+use rope::Rope;
+use super::async_read::{self, AsyncReader};
 use super::{ChannelBuffer, SharedBuffer, SharedWindow, return_buffer_to_window};
+use super::erased::{self, BoxError, ReceiverBuffer};
 use super::chunk::{self, Chunk};
+use super::chunks::{self, ChunkStream};
+#[cfg(feature = "futures03")]
+use super::compat::NotifyCache;
+use super::forward::{self, ForwardInto};
+use super::limit::{self, LimitReceiver};
+use super::observer::{self, WindowObserver};
+use super::super::{LimitExceeded, Never, Stalled, TimedOut};
+use super::head::{self, HeadReceiver, SharedGate};
+use super::stats::{self, ByteChannelStats, ChannelStats, SharedStats};
+#[cfg(feature = "timer")]
+use super::Timer;
+#[cfg(feature = "timer")]
+use super::timeout::{self, RecvTimeout};
+#[cfg(feature = "metrics")]
+use super::latency::ParkTimer;
 
 pub type PollChunk<E> = Result<Async<Option<Chunk>>, E>;
 
-pub fn new<E>(buffer: SharedBuffer<E>, window: SharedWindow) -> ByteReceiver<E> {
-    ByteReceiver { buffer, window }
+/// Indicates why `ByteReceiver::try_take_all` could not take the channel's data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TakeAllError<E> {
+    /// The sender has not closed the channel.
+    Open,
+
+    /// The sender failed the stream.
+    Failed(E),
 }
 
+/// The outcome of `ByteReceiver::poll_result`.
 #[derive(Debug)]
-pub struct ByteReceiver<E> {
+pub enum ChunkOrEnd<E> {
+    /// Data was read from the channel.
+    Chunk(Chunk),
+
+    /// The channel has ended.
+    End,
+
+    /// The sender failed the stream.
+    Error(E),
+}
+
+/// Indicates why `ByteReceiver::try_recv` did not return data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError<E> {
+    /// No data is buffered, but the sender may push more.
+    Empty,
+
+    /// The sender has closed the channel and all of its data has been read.
+    Closed,
+
+    /// The sender failed the stream.
+    Failed(E),
+}
+
+/// The result of `ByteReceiver::poll_chunk_no_park`.
+#[derive(Debug)]
+pub enum PollNoPark<E> {
+    /// Data was read from the channel.
+    Data(Chunk),
+
+    /// No data is available yet. The current task has not been saved, so the caller
+    /// must arrange to poll again.
+    Pending,
+
+    /// The channel has ended.
+    Closed,
+
+    /// The sender failed the stream.
+    Failed(E),
+}
+
+pub fn new<E>(
     buffer: SharedBuffer<E>,
     window: SharedWindow,
+    stats: SharedStats,
+    #[cfg(feature = "metrics")] label: &'static str,
+) -> ByteReceiver<E> {
+    ByteReceiver {
+        buffer: ReceiverBuffer::Shared(buffer),
+        window,
+        stats,
+        receivers: Arc::new(AtomicUsize::new(1)),
+        head: None,
+        chunk_alignment: 1,
+        max_segments: usize::MAX,
+        coalesce: false,
+        lifo: false,
+        park_on_empty: true,
+        staging_size: 0,
+        staged: None,
+        on_drain: None,
+        stalls: None,
+        #[cfg(feature = "checksum")]
+        checksum: Hasher::new(),
+        #[cfg(feature = "metrics")]
+        parked: ParkTimer::new("byte_channel_receiver_parked_seconds", label),
+        #[cfg(feature = "futures03")]
+        notify: NotifyCache::default(),
+    }
+}
+
+#[derive(Debug)]
+pub struct ByteReceiver<E> {
+    buffer: ReceiverBuffer<E>,
+    window: SharedWindow,
+    stats: SharedStats,
+    /// The number of receiver handles reading from the channel, i.e. this receiver and
+    /// any `HeadReceiver` split from it.
+    receivers: Arc<AtomicUsize>,
+    head: Option<SharedGate>,
+    chunk_alignment: usize,
+    max_segments: usize,
+    coalesce: bool,
+    lifo: bool,
+    park_on_empty: bool,
+    staging_size: usize,
+    staged: Option<Chunk>,
+    on_drain: Option<OnDrain>,
+    stalls: Option<StallDetector<E>>,
+    #[cfg(feature = "checksum")]
+    checksum: Hasher,
+    #[cfg(feature = "metrics")]
+    parked: ParkTimer,
+    #[cfg(feature = "futures03")]
+    pub(super) notify: NotifyCache,
 }
 
 impl<E> ByteReceiver<E> {
     pub fn shrink_window(&self, sz: usize) {
-        (*self.window.lock().expect("locking byte channel window")).shrink(sz);
+        let mut window = self.window.lock().expect("locking byte channel window");
+        (*window).shrink(sz);
+        #[cfg(feature = "record")]
+        (*window).record(::Op::Shrink(sz));
+    }
+
+    /// Offers the sender `sz` bytes of additional capacity.
+    ///
+    /// The increment is published by the `WindowAdvertiser`. Only the window is locked, so
+    /// growing the window may be freely interleaved with reads: reads never modify the
+    /// window, and chunks return their capacity through the same lock.
+    pub fn grow_window(&self, sz: usize) {
+        let mut window = self.window.lock().expect("locking byte channel window");
+        (*window).advertise_increment(sz);
+        #[cfg(feature = "record")]
+        (*window).record(::Op::Grow(sz));
+    }
+
+    /// The operations performed on the channel so far, each with the window's state
+    /// immediately after it.
+    ///
+    /// Every push, read through `poll_chunk`, return of capacity, shrink, grow, and
+    /// published increment is recorded, starting with the channel's creation. The
+    /// sequence may be replayed with `sync::replay`.
+    #[cfg(feature = "record")]
+    pub fn recorded_ops(&self) -> Vec<::Recorded> {
+        (*self.window.lock().expect("locking byte channel window")).recorded().to_vec()
+    }
+
+    /// A snapshot of the channel's activity, if the channel was built to collect stats.
+    pub fn stats(&self) -> Option<ByteChannelStats> {
+        self.stats.as_ref().map(|s| s.snapshot())
+    }
+
+    /// A snapshot of the channel's flow-control state and buffer.
+    pub fn channel_stats(&self) -> ChannelStats {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        let segments = (*buffer).as_ref().map(|b| b.segment_count()).unwrap_or(0);
+        let window = self.window.lock().expect("locking byte channel window");
+        stats::channel_stats(&window, Some(segments))
+    }
+
+    /// Creates a handle that observes the channel's window without keeping the channel
+    /// alive, e.g. for a metrics task.
+    pub fn window_observer(&self) -> WindowObserver {
+        observer::new(&self.window, self.stats.clone())
+    }
+
+    /// Causes `poll_chunk` to return chunks whose sizes are multiples of `alignment`.
+    ///
+    /// Reads wait until a full block is buffered. Once the sender closes, any trailing
+    /// partial block is returned. If `poll_chunk` is called with a `max_sz` smaller than
+    /// `alignment`, the chunk is not aligned.
+    ///
+    /// An alignment greater than 1 is advertised to the sender through
+    /// `ByteSender::preferred_push_size`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `alignment` is zero.
+    pub fn set_chunk_alignment(&mut self, alignment: usize) {
+        assert!(0 < alignment, "chunk alignment must be non-zero");
+        self.chunk_alignment = alignment;
+        let preferred = if alignment == 1 { None } else { Some(alignment) };
+        (*self.window.lock().expect("locking byte channel window"))
+            .set_preferred_push_size(preferred);
+    }
+
+    /// Changes the chunk alignment used by `poll_chunk`, and notifies a task parked in
+    /// `poll_chunk` if the buffered data now satisfies the new alignment.
+    ///
+    /// This lets a consumer that lowers its alignment while parked read the data that was
+    /// held back under the old one. Unlike `set_chunk_alignment`, the sender's
+    /// `preferred_push_size` is left unchanged.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `alignment` is zero.
+    pub fn set_chunk_alignment_and_wake(&mut self, alignment: usize) {
+        assert!(0 < alignment, "chunk alignment must be non-zero");
+        self.chunk_alignment = alignment;
+        let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+        if let Some(ChannelBuffer::Sending { ref buffers, ref mut awaiting_chunk }) = *buffer {
+            if alignment <= buffers.len() {
+                if let Some(t) = awaiting_chunk.take() {
+                    t.notify();
+                }
+            }
+        }
+    }
+
+    /// Limits the number of buffered segments assembled into a single chunk by
+    /// `poll_chunk`, bounding the work done while the channel is locked.
+    ///
+    /// Remaining segments are left for subsequent reads. If the limited segments hold
+    /// less than one aligned block, the limit is not applied.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn set_max_segments_per_chunk(&mut self, max: usize) {
+        assert!(0 < max, "max segments per chunk must be non-zero");
+        self.max_segments = max;
+    }
+
+    /// Causes `poll_chunk` to join multiple buffered segments into a single contiguous
+    /// segment.
+    ///
+    /// Coalescing copies every byte of a multi-segment read once, in exchange for chunks
+    /// that may be written with a single call.
+    pub fn set_coalesce_reads(&mut self, coalesce: bool) {
+        self.coalesce = coalesce;
+    }
+
+    /// Causes `poll_chunk` to read from the back of the buffer. Set by
+    /// `Builder::lifo`.
+    pub(super) fn set_lifo(&mut self, lifo: bool) {
+        self.lifo = lifo;
+    }
+
+    /// Determines whether `poll_chunk` and `poll_chunk_with_window` save the current task
+    /// when no data is available.
+    ///
+    /// When disabled, they return `NotReady` without arranging to be notified,
+    /// as `poll_chunk_no_park` does, and the caller is responsible for polling again.
+    /// This suits manual scheduling loops. Defaults to enabled.
+    pub fn set_park_on_empty(&mut self, park: bool) {
+        self.park_on_empty = park;
+    }
+
+    /// Causes `poll_read_into` to read up to `size` bytes from the channel at a time,
+    /// serving small reads from the retained chunk. A size of 0 disables staging.
+    ///
+    /// Staged bytes hold window capacity until they have been read.
+    pub fn set_read_staging(&mut self, size: usize) {
+        self.staging_size = size;
+    }
+
+    /// Causes reads to fail with `Stalled` after `polls` consecutive reads that could not
+    /// complete while the sender had no window available and no increment was pending.
+    ///
+    /// This turns a channel whose window is never opened, e.g. because its advertiser is
+    /// never polled, into an observable failure.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `polls` is zero.
+    pub fn detect_stalls(&mut self, polls: usize)
+    where
+        E: From<Stalled>,
+    {
+        assert!(0 < polls, "stall threshold must be non-zero");
+        self.stalls = Some(StallDetector {
+            limit: polls,
+            polls: 0,
+            error: Box::new(|| Stalled.into()),
+        });
+    }
+
+    /// Splits the channel into a receiver for the first `n` bytes and a receiver for
+    /// everything after them.
+    ///
+    /// The returned `ByteReceiver` does not yield any data until the `HeadReceiver` has
+    /// read all `n` bytes (or has been dropped, in which case the body resumes wherever the
+    /// head stopped).
+    ///
+    /// The channel is torn down only once both halves have been dropped, so the head may
+    /// finish reading after the body is dropped.
+    pub fn split_at_offset(mut self, n: usize) -> (HeadReceiver<E>, ByteReceiver<E>) {
+        self.receivers.fetch_add(1, Ordering::SeqCst);
+        let (head, gate) = head::new(
+            self.buffer.clone(),
+            self.window.clone(),
+            self.stats.clone(),
+            self.receivers.clone(),
+            n,
+        );
+        self.head = Some(gate);
+        (head, self)
+    }
+
+    /// The CRC32 of all bytes read from the channel.
+    #[cfg(feature = "checksum")]
+    pub fn checksum(&self) -> u32 {
+        self.checksum.clone().finalize()
+    }
+
+    /// The strong and weak reference counts of the channel's buffer.
+    #[cfg(test)]
+    pub(crate) fn buffer_refs(&self) -> (usize, usize) {
+        match self.buffer {
+            ReceiverBuffer::Shared(ref b) => (Arc::strong_count(b), Arc::weak_count(b)),
+            ReceiverBuffer::Erased(ref b) => (Arc::strong_count(b), Arc::weak_count(b)),
+        }
+    }
+
+    /// The strong and weak reference counts of the channel's window.
+    #[cfg(test)]
+    pub(crate) fn window_refs(&self) -> (usize, usize) {
+        (Arc::strong_count(&self.window), Arc::weak_count(&self.window))
+    }
+
+    pub(super) fn shared_window(&self) -> &SharedWindow {
+        &self.window
+    }
+
+    /// The lengths of the segments currently buffered in the channel, in read order.
+    #[cfg(feature = "debug-introspect")]
+    pub fn buffered_segments(&self) -> Vec<usize> {
+        match *self.buffer.lock().expect("locking byte channel buffer") {
+            Some(ChannelBuffer::Sending { ref buffers, .. }) |
+            Some(ChannelBuffer::SenderClosed { ref buffers, .. }) |
+            Some(ChannelBuffer::DrainingThenFailed { ref buffers, .. }) => {
+                buffers.iter().map(|b| b.len()).collect()
+            }
+            _ => Vec::new(),
+        }
     }
 }
 
 impl<E> Drop for ByteReceiver<E> {
     fn drop(&mut self) {
-        if let Ok(mut buffer) = self.buffer.lock() {
-            let b = (*buffer).take();
-            if b.is_none() {
-                return;
+        if self.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            lose_receiver(&self.buffer, &self.window);
+        }
+    }
+}
+
+/// Tears down a channel once its last receiver handle has been dropped.
+pub fn lose_receiver<E>(buffer: &ReceiverBuffer<E>, window: &SharedWindow) {
+    let mut buffer = buffer.lock().ok();
+    let mut buffered = 0;
+    if let Some(ref mut buffer) = buffer {
+        match **buffer {
+            // Preserve the cancellation or failure for the sender.
+            None |
+            Some(ChannelBuffer::ReceiverCancelled(_)) |
+            Some(ChannelBuffer::ReceiverFailed(_)) |
+            Some(ChannelBuffer::ReceiverDone) => {}
+            Some(_) => {
+                let state = buffer.replace(ChannelBuffer::LostReceiver);
+                buffered = state.map(|s| s.len()).unwrap_or(0);
             }
-            return_buffer_to_window(&buffer, &self.window);
-            *buffer = Some(ChannelBuffer::LostReceiver)
         }
     }
+
+    // Without a receiver, no further credit can be used. The buffered bytes' credit is
+    // returned under the same lock, before the window is closed, so that the advertiser
+    // publishes it before observing the close.
+    if let Ok(mut window) = window.lock() {
+        if 0 < buffered {
+            (*window).return_claimed(buffered);
+        }
+        (*window).close();
+    }
 }
 
 impl<E> ByteReceiver<E> {
     /// Poll at most `max_sz` bytes from the channel.
+    ///
+    /// ## Reentrancy
+    ///
+    /// A channel must not be polled from within one of its own polls, e.g. by an
+    /// `on_drain` callback that reads from a `HeadReceiver` split from this receiver.
+    /// Likewise, chunks should be dropped outside of the poll that produces the next
+    /// chunk, since dropping a chunk locks the window. In debug builds, reentrant polls
+    /// panic.
     pub fn poll_chunk(&mut self, max_sz: usize) -> PollChunk<E> {
+        let park = self.park_on_empty;
+        self.poll_aligned(max_sz, park, || {})
+    }
+
+    /// Poll at most `max_sz` bytes from the channel, reporting the end of the stream and
+    /// failures alike as outcomes rather than errors.
+    pub fn poll_result(&mut self, max_sz: usize) -> Poll<ChunkOrEnd<E>, Never> {
+        Ok(match self.poll_chunk(max_sz) {
+            Ok(Async::Ready(Some(chunk))) => Async::Ready(ChunkOrEnd::Chunk(chunk)),
+            Ok(Async::Ready(None)) => Async::Ready(ChunkOrEnd::End),
+            Ok(Async::NotReady) => Async::NotReady,
+            Err(e) => Async::Ready(ChunkOrEnd::Error(e)),
+        })
+    }
+
+    /// Indicates whether a read would make progress, without reading.
+    ///
+    /// Ready once at least one byte is buffered or the channel has ended or failed, so
+    /// the caller may then choose how much to read. With a chunk alignment, at least one
+    /// aligned chunk must be buffered, as `poll_chunk` returns no data until then.
+    /// Otherwise, the current task is saved to be notified when data is pushed, as by
+    /// `poll_chunk`.
+    pub fn poll_readable(&mut self) -> Async<()> {
+        let _guard = enter_poll(&self.buffer);
+        if self.poll_head(true).is_not_ready() {
+            return Async::NotReady;
+        }
+
+        let align = self.chunk_alignment;
+        let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::Sending { ref buffers, ref mut awaiting_chunk })
+                if buffers.len() < align =>
+            {
+                *awaiting_chunk = Some(task::current());
+                Async::NotReady
+            }
+            _ => Async::Ready(()),
+        }
+    }
+
+    /// Poll at most `max_sz` bytes from the channel without saving the current task.
+    ///
+    /// This is intended for poll loops that manage their own wakeups: when no data is
+    /// available, the channel does not notify anyone once data arrives.
+    pub fn poll_chunk_no_park(&mut self, max_sz: usize) -> PollNoPark<E> {
+        match self.poll_aligned(max_sz, false, || {}) {
+            Ok(Async::Ready(Some(chunk))) => PollNoPark::Data(chunk),
+            Ok(Async::Ready(None)) => PollNoPark::Closed,
+            Ok(Async::NotReady) => PollNoPark::Pending,
+            Err(e) => PollNoPark::Failed(e),
+        }
+    }
+
+    /// Reads at most `max_sz` bytes from the channel if any are buffered.
+    ///
+    /// Like `poll_chunk_no_park`, this never saves the current task, so it may be called
+    /// outside of a task, e.g. from a synchronous event loop.
+    pub fn try_recv(&mut self, max_sz: usize) -> Result<Chunk, TryRecvError<E>> {
+        match self.poll_chunk_no_park(max_sz) {
+            PollNoPark::Data(chunk) => Ok(chunk),
+            PollNoPark::Pending => Err(TryRecvError::Empty),
+            PollNoPark::Closed => Err(TryRecvError::Closed),
+            PollNoPark::Failed(e) => Err(TryRecvError::Failed(e)),
+        }
+    }
+
+    /// Reads bytes into `dst`, returning the number of bytes read, or 0 once the stream
+    /// has ended.
+    ///
+    /// If read staging is enabled, a chunk of up to the staging size is retained and
+    /// subsequent reads are served from it without polling the channel.
+    pub fn poll_read_into(&mut self, dst: &mut [u8]) -> Poll<usize, E> {
+        if dst.is_empty() {
+            return Ok(Async::Ready(0));
+        }
+
+        if self.staged.as_ref().map(|c| c.is_empty()).unwrap_or(true) {
+            let sz = cmp::max(dst.len(), self.staging_size);
+            self.staged = try_ready!(self.poll_chunk(sz));
+        }
+
+        match self.staged {
+            None => Ok(Async::Ready(0)),
+            Some(ref mut chunk) => {
+                let sz = cmp::min(dst.len(), chunk.remaining());
+                chunk.copy_to_slice(&mut dst[..sz]);
+                Ok(Async::Ready(sz))
+            }
+        }
+    }
+
+    /// Poll at most `max_sz` bytes from the channel, along with the sender's available
+    /// window at the time of the read.
+    ///
+    /// The window is observed while the channel's buffer is locked, so it is consistent
+    /// with the chunk that is returned.
+    pub fn poll_chunk_with_window(&mut self, max_sz: usize) -> Poll<Option<(Chunk, usize)>, E> {
+        let window = self.window.clone();
+        let park = self.park_on_empty;
+        let mut available = 0;
+        let chunk = try_ready!(self.poll_aligned(max_sz, park, || {
+            available = (*window.lock().expect("locking byte channel window")).available();
+        }));
+        Ok(Async::Ready(chunk.map(|c| (c, available))))
+    }
+
+    /// Polls a chunk, honoring the receiver's chunk alignment. `observe` is called while
+    /// the buffer is locked, before the chunk is taken.
+    fn poll_aligned<F>(&mut self, max_sz: usize, park: bool, observe: F) -> PollChunk<E>
+    where
+        F: FnOnce(),
+    {
         if max_sz == 0 {
+            observe();
             return Ok(Async::Ready(Some(chunk::empty())));
         }
 
-        let chunk = {
-            let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
-            match (*buffer).take() {
-                None => {
-                    return Ok(Async::Ready(None));
+        let align = self.chunk_alignment;
+        let max_segments = self.max_segments;
+        let lifo = self.lifo;
+        let take_sz = |buffers: &Rope, len: usize, closed: bool| {
+            observe();
+            // The segment limit applies to whichever end of the buffer is read.
+            let segments_sz = if lifo {
+                buffers.iter().rev().take(max_segments).fold(0, |sz, b| sz + b.len())
+            } else {
+                buffers.iter().take(max_segments).fold(0, |sz, b| sz + b.len())
+            };
+            let max_sz = if segments_sz < align {
+                max_sz
+            } else {
+                cmp::min(max_sz, segments_sz)
+            };
+            let sz = cmp::min(len, max_sz);
+            match sz - sz % align {
+                0 if closed || max_sz < align => Some(sz),
+                0 => None,
+                aligned => Some(aligned),
+            }
+        };
+
+        // Capacity will be increased as the chunk is consumed.
+        let buffers = try_ready!(self.poll_read(park, lifo, take_sz));
+        let chunk = buffers.map(|b| {
+            let chunk = if self.coalesce {
+                chunk::from_bytes(&self.window, b.into_bytes())
+            } else {
+                chunk::from_rope(&self.window, b)
+            };
+            chunk::track(chunk, &self.stats)
+        });
+        #[cfg(feature = "record")]
+        (*self.window.lock().expect("locking byte channel window")).record(::Op::PollChunk {
+            max_sz,
+            len: chunk.as_ref().map(|c| c.len()),
+        });
+        Ok(Async::Ready(chunk))
+    }
+
+    /// Poll at most `max_sz` bytes from the channel, failing if no data is ready by
+    /// `deadline`.
+    ///
+    /// The deadline is only checked when the receiver is polled; no timer is registered,
+    /// so the caller must arrange to be polled again once the deadline passes.
+    pub fn poll_chunk_until(&mut self, max_sz: usize, deadline: Instant) -> PollChunk<E>
+    where
+        E: From<TimedOut>,
+    {
+        match self.poll_chunk(max_sz)? {
+            Async::NotReady if deadline <= Instant::now() => Err(TimedOut.into()),
+            ready => Ok(ready),
+        }
+    }
+
+    /// Reads at most `max_sz` bytes from the channel, failing with `TimedOut` if no data
+    /// is ready within `timeout`, as measured by `timer`.
+    ///
+    /// The future resolves to `None` once the stream has ended.
+    #[cfg(feature = "timer")]
+    pub fn recv_timeout<'a, T: Timer>(
+        &'a mut self,
+        max_sz: usize,
+        timeout: Duration,
+        timer: &T,
+    ) -> RecvTimeout<'a, E, T>
+    where
+        E: From<TimedOut>,
+    {
+        timeout::new(self, max_sz, timeout, timer)
+    }
+
+    /// Forgets the task saved by a read that was not ready, so that pushes do not
+    /// notify a task that is no longer waiting on the channel.
+    #[cfg(feature = "timer")]
+    pub(super) fn unpark(&mut self) {
+        let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+        if let Some(ChannelBuffer::Sending { ref mut awaiting_chunk, .. }) = *buffer {
+            *awaiting_chunk = None;
+        }
+    }
+
+    /// Poll exactly `n` bytes from the channel, waiting until at least `n` bytes are
+    /// buffered, e.g. to read a fixed-size header.
+    ///
+    /// If the sender closes while fewer than `n` bytes are buffered, the remaining bytes
+    /// are returned, so the chunk may be shorter than `n`; once nothing remains, the
+    /// stream has ended. The receiver's chunk alignment is ignored.
+    pub fn poll_chunk_exact(&mut self, n: usize) -> PollChunk<E> {
+        if n == 0 {
+            return Ok(Async::Ready(Some(chunk::empty())));
+        }
+
+        let buffers = try_ready!(self.poll_read(true, false, |_, len, closed| {
+            if n <= len {
+                Some(n)
+            } else if closed {
+                Some(len)
+            } else {
+                None
+            }
+        }));
+        Ok(Async::Ready(buffers.map(|b| {
+            chunk::track(chunk::from_rope(&self.window, b), &self.stats)
+        })))
+    }
+
+    /// Poll every byte currently buffered in the channel as a single chunk, e.g. to
+    /// forward it to a downstream that does its own buffering.
+    ///
+    /// The chunk is bounded only by the window, since the sender may not buffer more
+    /// than it allows. The receiver's chunk alignment and segment limit are ignored. As
+    /// with `poll_chunk`, capacity is returned to the window as the chunk is consumed.
+    pub fn poll_all(&mut self) -> PollChunk<E> {
+        let buffers = try_ready!(self.poll_read(true, false, |_, len, _| Some(len)));
+        Ok(Async::Ready(buffers.map(|b| {
+            chunk::track(chunk::from_rope(&self.window, b), &self.stats)
+        })))
+    }
+
+    /// Poll for the bytes up to and including the next `delim`.
+    ///
+    /// At most `max` bytes are returned, so if `delim` does not occur within the first
+    /// `max` bytes, those bytes are returned without it. Once the sender has closed, the
+    /// remaining bytes are returned whether or not they end with `delim`.
+    ///
+    /// Unlike a `Chunk`, the returned bytes are contiguous, so bytes spanning several
+    /// segments are copied. The window is opened as soon as the bytes are returned.
+    pub fn poll_until(&mut self, delim: u8, max: usize) -> Poll<Option<Bytes>, E> {
+        if max == 0 {
+            return Ok(Async::Ready(Some(Bytes::new())));
+        }
+
+        let buffers = try_ready!(self.poll_read(true, false, |buffers, len, closed| {
+            let limit = cmp::min(len, max);
+            let found = buffers
+                .iter()
+                .flat_map(|b| b.iter())
+                .take(limit)
+                .position(|b| *b == delim);
+            match found {
+                Some(pos) => Some(pos + 1),
+                None if limit == max || closed => Some(limit),
+                None => None,
+            }
+        }));
+
+        Ok(Async::Ready(buffers.map(|buffers| {
+            let bytes = buffers.into_bytes();
+            self.open_window(bytes.len());
+            bytes
+        })))
+    }
+
+    /// Calls `f` with the contiguous bytes at the front of the channel.
+    ///
+    /// `f` returns the number of bytes it consumed, which are removed from the channel and
+    /// returned to the window, along with a result. This avoids constructing a `Chunk`
+    /// for callers that can process data one segment at a time.
+    ///
+    /// `f` is called after the channel has been unlocked, so it may use the channel's
+    /// other handles, e.g. to push a response.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `f` consumes more bytes than it was given.
+    pub fn with_front<F, R>(&mut self, f: F) -> Poll<Option<R>, E>
+    where
+        F: FnOnce(&[u8]) -> (usize, R),
+    {
+        let mut front = None;
+        try_ready!(self.poll_read(true, false, |buffers, _, _| {
+            front = buffers.front().cloned();
+            Some(0)
+        }));
+        let front = match front {
+            Some(front) => front,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let (sz, r) = f(&front);
+        assert!(sz <= front.len(), "consumed more than the front segment");
+
+        // Only the receiver removes data, so the front still holds the bytes `f` saw,
+        // unless the sender has since discarded them by failing the stream.
+        if 0 < sz {
+            let taken = try_ready!(self.poll_read(false, false, |_, len, _| Some(cmp::min(sz, len))));
+            if let Some(taken) = taken {
+                self.open_window(taken.len());
+            }
+        }
+
+        Ok(Async::Ready(Some(r)))
+    }
+
+    /// Converts this receiver into a `Stream` of the buffered segments.
+    ///
+    /// Each segment is yielded as it was pushed (or as much of it as remains), without
+    /// copying, and its bytes are returned to the window as soon as it's yielded.
+    pub fn into_stream(mut self) -> impl Stream<Item = Bytes, Error = E> {
+        stream::poll_fn(move || {
+            let front_len = |buffers: &Rope, _, _| buffers.front().map(|b| b.len());
+            let buffers = try_ready!(self.poll_read(true, false, front_len));
+            Ok(Async::Ready(buffers.map(|buffers| {
+                let bytes = buffers.into_bytes();
+                self.open_window(bytes.len());
+                bytes
+            })))
+        })
+    }
+
+    /// Converts this receiver into a `Stream` of chunks of at most `max_sz` bytes.
+    ///
+    /// The stream ends once the sender has closed the channel and all buffered bytes
+    /// have been read.
+    pub fn into_chunks(self, max_sz: usize) -> ChunkStream<E> {
+        chunks::new(self, max_sz)
+    }
+
+    /// Converts this receiver into an `AsyncReader` that reads the channel's data
+    /// through `std::io::Read`.
+    pub fn into_async_read(self) -> AsyncReader<E> {
+        async_read::new(self)
+    }
+
+    /// Limits the total number of bytes that may be read from the channel, e.g. to
+    /// enforce a maximum body size.
+    ///
+    /// Reads fail with `LimitExceeded` once the channel carries more than `max_total`
+    /// bytes.
+    pub fn limit(self, max_total: u64) -> LimitReceiver<E>
+    where
+        E: From<LimitExceeded>,
+    {
+        limit::new(self, max_total)
+    }
+
+    /// Forwards the channel's data into `sink`, one segment at a time and without
+    /// copying.
+    ///
+    /// Each segment's capacity is returned to the window once the sink has accepted it,
+    /// so the sink's backpressure applies to the channel's sender. The returned future
+    /// completes with the sink once the channel has ended and the sink has been flushed.
+    pub fn forward_into<S>(self, sink: S) -> ForwardInto<E, S>
+    where
+        S: Sink<SinkItem = Bytes>,
+        E: From<S::SinkError>,
+    {
+        forward::new(self, sink)
+    }
+
+    /// Converts the receiver into one whose errors are boxed, so that receivers with
+    /// different error types may be stored together.
+    ///
+    /// The receiver's settings and any staged or unread data carry over. The sender is
+    /// unaffected: it still fails the stream with `E`, which the receiver reads boxed. If
+    /// the receiver cancels the stream with an error that is not an `E`, the sender's
+    /// push fails with `PushError::Cancelled` but `take_cancellation` returns `None`.
+    pub fn erase_error(mut self) -> ByteReceiver<Box<dyn Error + Send>>
+    where
+        E: Error + Send + 'static,
+    {
+        // The new receiver takes over this handle, so dropping this one must not tear
+        // down the channel.
+        self.receivers.fetch_add(1, Ordering::SeqCst);
+        let stalls = self.stalls.take().map(|stalls| {
+            let error = stalls.error;
+            StallDetector {
+                limit: stalls.limit,
+                polls: stalls.polls,
+                error: Box::new(move || Box::new(error()) as BoxError),
+            }
+        });
+        let buffer = erased::BoxErrors(self.buffer.clone());
+        ByteReceiver {
+            buffer: ReceiverBuffer::Erased(Arc::new(buffer)),
+            window: self.window.clone(),
+            stats: self.stats.clone(),
+            receivers: self.receivers.clone(),
+            head: self.head.take(),
+            chunk_alignment: self.chunk_alignment,
+            max_segments: self.max_segments,
+            coalesce: self.coalesce,
+            lifo: self.lifo,
+            park_on_empty: self.park_on_empty,
+            staging_size: self.staging_size,
+            staged: self.staged.take(),
+            on_drain: self.on_drain.take(),
+            stalls,
+            #[cfg(feature = "checksum")]
+            checksum: self.checksum.clone(),
+            #[cfg(feature = "metrics")]
+            parked: self.parked.clone(),
+            #[cfg(feature = "futures03")]
+            notify: NotifyCache::default(),
+        }
+    }
+
+    /// Takes all of the channel's data at once, if the sender has already closed.
+    ///
+    /// The data is returned as a single contiguous `Bytes`, which is copied if it spans
+    /// several segments, and the window is opened immediately. Unlike `poll_chunk`, this
+    /// never waits and may be called outside of a task.
+    pub fn try_take_all(&mut self) -> Result<Bytes, TakeAllError<E>> {
+        match self.poll_read(false, false, |_, len, closed| if closed { Some(len) } else { None }) {
+            Ok(Async::Ready(Some(buffers))) => {
+                let bytes = buffers.into_bytes();
+                self.open_window(bytes.len());
+                Ok(bytes)
+            }
+            Ok(Async::Ready(None)) => Ok(Bytes::new()),
+            Ok(Async::NotReady) => Err(TakeAllError::Open),
+            Err(e) => Err(TakeAllError::Failed(e)),
+        }
+    }
+
+    /// A copy of the channel's unread data, if the sender has closed the channel so that
+    /// all of it is buffered.
+    ///
+    /// The channel is left unchanged. Returns `None` while a `HeadReceiver` split from
+    /// this receiver has not been fully read.
+    pub fn to_bytes_if_complete(&self) -> Option<Bytes> {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::SenderClosed { ref buffers }) if self.head.is_none() => {
+                Some(buffers.clone().into_bytes())
+            }
+            _ => None,
+        }
+    }
+
+    /// Cancels the stream, causing the sender's next push to fail with
+    /// `PushError::Cancelled`. The sender may obtain `e` with
+    /// `ByteSender::take_cancellation`.
+    ///
+    /// Any buffered data is discarded and returned to the window, and subsequent reads
+    /// return `None`. A sender waiting in `poll_capacity` or `poll_drained` is notified
+    /// so that it observes the cancellation.
+    pub fn cancel(&mut self, e: E) {
+        self.stop_reading(ChannelBuffer::ReceiverCancelled(Some(e)));
+    }
+
+    /// Fails the stream from the receiving side, e.g. after detecting a protocol
+    /// violation, as `ByteSender::reset` does from the sending side.
+    ///
+    /// The sender's next push fails with `PushError::ReceiverFailed`, so that it can tell
+    /// the failure apart from a cancellation, and the sender may obtain `e` with
+    /// `ByteSender::take_cancellation`. Buffered data that has not been read is discarded
+    /// and its capacity returned to the window immediately, while chunks that have
+    /// already been read return their capacity as they are consumed or dropped. Waiting
+    /// senders are notified.
+    pub fn reset(mut self, e: E) {
+        self.stop_reading(ChannelBuffer::ReceiverFailed(Some(e)));
+    }
+
+    /// Indicates that the receiver won't read any more data, causing the sender's next
+    /// push to fail with `PushError::ReceiverDone`.
+    ///
+    /// Unlike `cancel`, no error is reported to the sender, and unlike dropping the
+    /// receiver, the channel's handles remain usable for an orderly teardown. Any
+    /// buffered data is discarded and returned to the window, and subsequent reads
+    /// return `None`. As with `cancel`, waiting senders are notified.
+    pub fn half_close(&mut self) {
+        self.stop_reading(ChannelBuffer::ReceiverDone);
+    }
+
+    /// Discards any buffered data and leaves `state` for the sender, unless the stream
+    /// has already ended.
+    fn stop_reading(&mut self, state: ChannelBuffer<E>) {
+        self.on_drain = None;
+        let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::Sending { .. }) |
+            Some(ChannelBuffer::SenderClosed { .. }) |
+            Some(ChannelBuffer::DrainingThenFailed { .. }) => {
+                return_buffer_to_window(&buffer, &self.window);
+                *buffer = Some(state);
+                (*self.window.lock().expect("locking byte channel window")).wake_senders();
+            }
+            _ => {}
+        }
+    }
+
+    /// Registers a callback to be invoked once the sender has closed and all data has
+    /// been read.
+    ///
+    /// The callback is not invoked if the sender fails the stream or if the receiver is
+    /// dropped before reaching the end of the stream.
+    pub fn on_drain<F>(&mut self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.on_drain = Some(OnDrain(Box::new(f)));
+    }
+
+    /// Removes bytes from the front of the channel, as described by `poll_take`.
+    ///
+    /// Every read goes through here, so this is where reentrant polls are detected.
+    fn poll_read<F>(
+        &mut self,
+        park: bool,
+        from_back: bool,
+        take_sz: F,
+    ) -> Poll<Option<Rope>, E>
+    where
+        F: FnOnce(&Rope, usize, bool) -> Option<usize>,
+    {
+        let _guard = enter_poll(&self.buffer);
+        if self.poll_head(park).is_not_ready() {
+            return Ok(Async::NotReady);
+        }
+
+        match poll_take(&self.buffer, park, from_back, take_sz) {
+            Ok(Async::Ready(Some(buffers))) => {
+                #[cfg(feature = "checksum")]
+                for bytes in &buffers {
+                    self.checksum.update(bytes);
+                }
+                if let Some(ref mut stalls) = self.stalls {
+                    stalls.polls = 0;
                 }
+                #[cfg(feature = "metrics")]
+                self.parked.wake();
+                Ok(Async::Ready(Some(buffers)))
+            }
 
-                Some(ChannelBuffer::LostReceiver) => unreachable!(),
-                Some(ChannelBuffer::SenderFailed(e)) => {
-                    return Err(e);
+            Ok(Async::Ready(None)) => {
+                if let Some(OnDrain(f)) = self.on_drain.take() {
+                    f();
                 }
+                Ok(Async::Ready(None))
+            }
 
-                Some(ChannelBuffer::Sending {
-                         mut len,
-                         mut buffers,
-                         ..
-                     }) => {
-                    // If there's no data, wait for some.
-                    if len == 0 {
-                        *buffer = Some(ChannelBuffer::Sending {
-                            len,
-                            buffers,
-                            awaiting_chunk: Some(task::current()),
-                        });
-                        return Ok(Async::NotReady);
+            Ok(Async::NotReady) => {
+                if park {
+                    if let Some(ref stats) = self.stats {
+                        stats.blocked();
                     }
+                    #[cfg(feature = "metrics")]
+                    self.parked.park();
+                }
+                if let Some(ref mut stalls) = self.stalls {
+                    let starved = {
+                        let window = self.window.lock().expect("locking byte channel window");
+                        let snapshot = (*window).snapshot();
+                        snapshot.advertised == 0 && snapshot.pending == 0
+                    };
+                    if !starved {
+                        stalls.polls = 0;
+                    } else {
+                        stalls.polls += 1;
+                        if stalls.limit <= stalls.polls {
+                            self.on_drain = None;
+                            return Err((stalls.error)());
+                        }
+                    }
+                }
+                Ok(Async::NotReady)
+            }
 
-                    let sz = cmp::min(len, max_sz);
-                    debug_assert!(sz != 0);
+            Err(e) => {
+                self.on_drain = None;
+                Err(e)
+            }
+        }
+    }
 
-                    // Capacity will be increased as the chunk is consumed.
-                    len -= sz;
-                    let chunk = Self::assemble_chunk(&self.window, &mut buffers, sz);
+    /// Returns bytes that have been consumed without a `Chunk` to the window.
+    fn open_window(&self, sz: usize) {
+        if let Some(ref stats) = self.stats {
+            stats.consumed(sz);
+        }
+        (*self.window.lock().expect("locking byte channel window")).return_claimed(sz);
+    }
 
+    /// Waits for a `HeadReceiver` split from this receiver to finish reading.
+    fn poll_head(&mut self, park: bool) -> Async<()> {
+        if let Some(gate) = self.head.take() {
+            if head::poll_released(&gate, park).is_not_ready() {
+                self.head = Some(gate);
+                return Async::NotReady;
+            }
+        }
+        Async::Ready(())
+    }
+}
+
+/// Serializes the data of a complete channel, as returned by
+/// `ByteReceiver::to_bytes_if_complete`, as bytes.
+///
+/// Fails if the channel is not complete.
+#[cfg(feature = "serde")]
+impl<E> ::serde::Serialize for ByteReceiver<E> {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+        match self.to_bytes_if_complete() {
+            Some(bytes) => serializer.serialize_bytes(&bytes),
+            None => Err(S::Error::custom("byte channel is not complete")),
+        }
+    }
+}
+
+/// Counts consecutive reads that waited on a starved window.
+struct StallDetector<E> {
+    limit: usize,
+    polls: usize,
+    error: Box<dyn Fn() -> E + Send>,
+}
+
+impl<E> fmt::Debug for StallDetector<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StallDetector")
+            .field("limit", &self.limit)
+            .field("polls", &self.polls)
+            .finish()
+    }
+}
+
+/// A callback invoked when a receiver reaches the end of the stream.
+struct OnDrain(Box<dyn FnOnce() + Send>);
+
+impl fmt::Debug for OnDrain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("OnDrain")
+    }
+}
+
+thread_local!(static POLLING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) });
+
+/// Marks a channel as being polled on the current thread until dropped.
+pub struct PollGuard(usize);
+
+/// Marks `buffer`'s channel as being polled.
+///
+/// ## Panics
+///
+/// In debug builds, panics if the channel is already being polled on this thread.
+pub fn enter_poll<E>(buffer: &ReceiverBuffer<E>) -> PollGuard {
+    let key = buffer.key();
+    if cfg!(debug_assertions) {
+        POLLING.with(|polling| {
+            let mut polling = polling.borrow_mut();
+            assert!(!polling.contains(&key), "byte channel polled reentrantly");
+            polling.push(key);
+        });
+    }
+    PollGuard(key)
+}
+
+impl Drop for PollGuard {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            POLLING.with(|polling| polling.borrow_mut().retain(|k| *k != self.0));
+        }
+    }
+}
+
+/// Polls at most `max_sz` bytes from a channel's buffer.
+pub fn poll_buffer<E>(
+    buffer: &ReceiverBuffer<E>,
+    window: &SharedWindow,
+    max_sz: usize,
+) -> PollChunk<E> {
+    if max_sz == 0 {
+        return Ok(Async::Ready(Some(chunk::empty())));
+    }
+
+    // Capacity will be increased as the chunk is consumed.
+    let buffers = try_ready!(poll_take(buffer, true, false, |_, len, _| Some(cmp::min(len, max_sz))));
+    Ok(Async::Ready(buffers.map(|b| chunk::from_rope(window, b))))
+}
+
+/// Removes bytes from the front, or if `from_back` is set the back, of a channel's
+/// buffer.
+///
+/// When data is buffered, `take_sz` is called with the buffered segments, the number of
+/// buffered bytes, and whether the sender has closed, and returns the number of bytes to
+/// remove. If the buffer is empty or `take_sz` returns `None` while the sender is still
+/// sending, the read is not ready and, if `park` is set, the current task is saved to be
+/// notified when more data is pushed. Once the sender has closed, `take_sz` must not
+/// return `None`.
+fn poll_take<E, F>(
+    buffer: &ReceiverBuffer<E>,
+    park: bool,
+    from_back: bool,
+    take_sz: F,
+) -> Poll<Option<Rope>, E>
+where
+    F: FnOnce(&Rope, usize, bool) -> Option<usize>,
+{
+    let mut buffer = buffer.lock().expect("locking byte channel buffer");
+    match (*buffer).take() {
+        None => Ok(Async::Ready(None)),
+
+        Some(ChannelBuffer::LostReceiver) => {
+            // Receivers never observe their own loss, since the state is only set once
+            // every receiver handle has been dropped.
+            *buffer = Some(ChannelBuffer::LostReceiver);
+            Ok(Async::Ready(None))
+        }
+
+        Some(ChannelBuffer::SenderFailed(e)) => Err(e),
+
+        Some(ChannelBuffer::ReceiverCancelled(e)) => {
+            *buffer = Some(ChannelBuffer::ReceiverCancelled(e));
+            Ok(Async::Ready(None))
+        }
+
+        Some(ChannelBuffer::ReceiverFailed(e)) => {
+            *buffer = Some(ChannelBuffer::ReceiverFailed(e));
+            Ok(Async::Ready(None))
+        }
+
+        Some(ChannelBuffer::ReceiverDone) => {
+            *buffer = Some(ChannelBuffer::ReceiverDone);
+            Ok(Async::Ready(None))
+        }
+
+        Some(ChannelBuffer::Sending {
+                 mut buffers,
+                 awaiting_chunk,
+             }) => {
+            let len = buffers.len();
+            let sz = if len == 0 {
+                None
+            } else {
+                take_sz(&buffers, len, false)
+            };
+
+            // If there's not enough data, wait for more.
+            let sz = match sz {
+                Some(sz) => sz,
+                None => {
                     *buffer = Some(ChannelBuffer::Sending {
-                        len,
                         buffers,
-                        awaiting_chunk: None,
+                        awaiting_chunk: if park {
+                            Some(task::current())
+                        } else {
+                            awaiting_chunk
+                        },
                     });
-
-                    chunk
+                    return Ok(Async::NotReady);
                 }
+            };
 
-                Some(ChannelBuffer::SenderClosed {
-                         mut buffers,
-                         mut len,
-                     }) => {
-                    if len == 0 {
-                        *buffer = None;
-                        return Ok(Async::Ready(None));
-                    }
+            let taken = split(&mut buffers, sz, from_back);
 
-                    let sz = cmp::min(len, max_sz);
-                    debug_assert!(sz != 0);
-                    let chunk = Self::assemble_chunk(&self.window, &mut buffers, sz);
+            *buffer = Some(ChannelBuffer::Sending {
+                buffers,
+                awaiting_chunk: None,
+            });
 
-                    len -= sz;
-                    *buffer = {
-                        if len == 0 {
-                            None
-                        } else {
-                            Some(ChannelBuffer::SenderClosed { buffers, len })
-                        }
-                    };
+            Ok(Async::Ready(Some(taken)))
+        }
 
-                    chunk
-                }
+        Some(ChannelBuffer::SenderClosed { mut buffers }) => {
+            let len = buffers.len();
+            if len == 0 {
+                return Ok(Async::Ready(None));
             }
-        };
 
-        Ok(Async::Ready(Some(chunk)))
-    }
-
-    fn assemble_chunk(
-        window: &SharedWindow,
-        buffers: &mut VecDeque<Bytes>,
-        mut sz: usize,
-    ) -> Chunk {
-        let mut chunk = VecDeque::new();
-        while sz != 0 {
-            match buffers.pop_front() {
-                None => break,
-                Some(mut bytes) => {
-                    if sz < bytes.len() {
-                        // If the buffer is larger than the needed number of bytes, save the
-                        // beginning to be returned and put the rest of it back in the buffers
-                        // queue.
-                        let rest = bytes.split_off(sz);
-                        buffers.push_front(rest);
-                    }
-                    sz -= bytes.len();
-                    chunk.push_back(bytes);
-                }
+            let sz = take_sz(&buffers, len, true).expect("closed channel must be drained");
+            let taken = split(&mut buffers, sz, from_back);
+
+            if !buffers.is_empty() {
+                *buffer = Some(ChannelBuffer::SenderClosed { buffers });
             }
+
+            Ok(Async::Ready(Some(taken)))
         }
-        chunk::from_vec(window, chunk)
+
+        Some(ChannelBuffer::DrainingThenFailed { mut buffers, error }) => {
+            let len = buffers.len();
+            if len == 0 {
+                return Err(error);
+            }
+
+            let sz = take_sz(&buffers, len, true).expect("failing channel must be drained");
+            let taken = split(&mut buffers, sz, from_back);
+
+            // Once the buffered data has been read, the next read fails.
+            *buffer = Some(if buffers.is_empty() {
+                ChannelBuffer::SenderFailed(error)
+            } else {
+                ChannelBuffer::DrainingThenFailed { buffers, error }
+            });
+
+            Ok(Async::Ready(Some(taken)))
+        }
+    }
+}
+
+/// Removes `sz` bytes from the front of `buffers`, or from the back if `from_back` is set.
+fn split(buffers: &mut Rope, sz: usize, from_back: bool) -> Rope {
+    if from_back {
+        buffers.split_back(sz)
+    } else {
+        buffers.split_to(sz)
     }
 }
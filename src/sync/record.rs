@@ -0,0 +1,73 @@
+use bytes::{Buf, Bytes};
+use futures::*;
+use std::collections::VecDeque;
+
+use {Op, Recorded};
+use super::{Builder, Chunk};
+
+/// Replays a sequence of operations recorded by `ByteReceiver::recorded_ops` against a
+/// new channel, panicking if the window's state diverges from the recording.
+///
+/// Pushed bytes are zeroed, since only their lengths are recorded. Capacity is returned
+/// by advancing the chunks read during the replay, so the recording must not include
+/// capacity returned by dropping the receiver.
+pub fn replay(ops: &[Recorded]) {
+    let (window_size, burst_size) = match ops.first().map(|r| r.op) {
+        Some(Op::Open { window_size, burst_size }) => (window_size, burst_size),
+        op => panic!("recording must begin with the channel being opened: {:?}", op),
+    };
+
+    let mut task = executor::spawn(future::lazy(move || {
+        let (mut wx, mut tx, mut rx) = Builder::new()
+            .window_size(window_size)
+            .burst_window_size(burst_size)
+            .build::<()>();
+        let mut held: VecDeque<Chunk> = VecDeque::new();
+
+        for (i, expected) in ops.iter().enumerate() {
+            match expected.op {
+                Op::Open { .. } if i == 0 => {}
+                Op::Open { .. } => panic!("op {}: channel opened twice", i),
+
+                Op::Push(sz) => {
+                    if let Err(e) = tx.push_bytes(Bytes::from(vec![0; sz])) {
+                        panic!("op {}: push failed: {:?}", i, e);
+                    }
+                }
+
+                Op::PollChunk { max_sz, len } => match rx.poll_chunk(max_sz) {
+                    Ok(Async::Ready(Some(chunk))) => {
+                        assert_eq!(Some(chunk.len()), len, "op {}: chunk length", i);
+                        held.push_back(chunk);
+                    }
+                    Ok(Async::Ready(None)) => assert_eq!(len, None, "op {}: end of stream", i),
+                    poll => panic!("op {}: expected chunk of {:?} bytes: {:?}", i, len, poll),
+                },
+
+                Op::Advance(sz) => {
+                    let pos = held.iter().position(|c| c.len() >= sz);
+                    let pos = pos.unwrap_or_else(|| panic!("op {}: no chunk holds {} bytes", i, sz));
+                    held[pos].advance(sz);
+                    if held[pos].is_empty() {
+                        held.remove(pos);
+                    }
+                }
+
+                Op::Shrink(sz) => rx.shrink_window(sz),
+                Op::Grow(sz) => rx.grow_window(sz),
+
+                Op::Increment(sz) => match wx.poll() {
+                    Ok(Async::Ready(Some(incr))) => assert_eq!(incr, sz, "op {}: increment", i),
+                    poll => panic!("op {}: expected increment of {}: {:?}", i, sz, poll),
+                },
+            }
+
+            let replayed = rx.recorded_ops();
+            assert_eq!(replayed.len(), i + 1, "op {}: {:?} was not recorded", i, expected.op);
+            assert_eq!(replayed[i], *expected, "op {}: window state diverged", i);
+        }
+
+        Ok::<(), ()>(())
+    }));
+    task.wait_future().expect("replaying recorded operations");
+}
@@ -1,21 +1,234 @@
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
+#[cfg(feature = "checksum")]
+use crc32fast::Hasher;
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, try_ready};
+use std::cmp;
+use std::sync::Arc;
+#[cfg(feature = "checksum")]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use super::{ChannelBuffer, SharedBuffer, SharedWindow, return_buffer_to_window};
-use super::super::LostReceiver;
+use super::{ChannelBuffer, PushAfterClose, SharedBuffer, SharedWindow, return_buffer_to_window};
+use super::stats::{self, ByteChannelStats, ChannelStats, SharedStats};
+use super::super::{LimitExceeded, PushError};
+use rope::Rope;
+use super::async_write::{self, AsyncWriter};
+#[cfg(feature = "metrics")]
+use super::latency::ParkTimer;
 
-pub fn new<E>(buffer: SharedBuffer<E>, window: SharedWindow) -> ByteSender<E> {
-    ByteSender { buffer, window }
+pub fn new<E>(
+    buffer: SharedBuffer<E>,
+    window: SharedWindow,
+    stats: SharedStats,
+    push_after_close: PushAfterClose,
+    warn_buffered_above: Option<(usize, fn(usize))>,
+    max_message: Option<usize>,
+    coalesce_limit: Option<usize>,
+) -> ByteSender<E> {
+    ByteSender {
+        buffer,
+        window,
+        stats,
+        reserved: AtomicUsize::new(0),
+        senders: Arc::new(AtomicUsize::new(1)),
+        push_after_close,
+        warn_buffered_above,
+        max_message,
+        coalesce_limit,
+        on_total_limit: None,
+        copy_pool: BytesMut::new(),
+        copy_pool_size: 0,
+        unsent: Bytes::new(),
+        #[cfg(feature = "checksum")]
+        checksum: Arc::new(Mutex::new(Hasher::new())),
+        #[cfg(feature = "metrics")]
+        parked: ParkTimer::new("byte_channel_sender_parked_seconds", ""),
+    }
 }
 
+/// Pushes bytes into a channel.
+///
+/// Senders may be cloned. The channel is closed when any sender calls `close` or once all
+/// senders have been dropped.
 #[derive(Debug)]
 pub struct ByteSender<E> {
     buffer: SharedBuffer<E>,
     window: SharedWindow,
+    stats: SharedStats,
+    reserved: AtomicUsize,
+    senders: Arc<AtomicUsize>,
+    push_after_close: PushAfterClose,
+    warn_buffered_above: Option<(usize, fn(usize))>,
+    max_message: Option<usize>,
+    /// Pushes are joined onto the last buffered segment up to this size.
+    coalesce_limit: Option<usize>,
+    on_total_limit: Option<fn(LimitExceeded) -> E>,
+    copy_pool: BytesMut,
+    copy_pool_size: usize,
+    /// Bytes accepted by `Sink::start_send` that did not yet fit in the window.
+    unsent: Bytes,
+    /// Shared by every clone, and updated under the buffer lock so that it follows the
+    /// order in which bytes are buffered.
+    #[cfg(feature = "checksum")]
+    checksum: Arc<Mutex<Hasher>>,
+    #[cfg(feature = "metrics")]
+    parked: ParkTimer,
+}
+
+impl<E> Clone for ByteSender<E> {
+    fn clone(&self) -> Self {
+        self.senders.fetch_add(1, Ordering::SeqCst);
+        ByteSender {
+            buffer: self.buffer.clone(),
+            window: self.window.clone(),
+            stats: self.stats.clone(),
+            reserved: AtomicUsize::new(0),
+            senders: self.senders.clone(),
+            push_after_close: self.push_after_close,
+            warn_buffered_above: self.warn_buffered_above,
+            max_message: self.max_message,
+            coalesce_limit: self.coalesce_limit,
+            on_total_limit: self.on_total_limit,
+            copy_pool: BytesMut::new(),
+            copy_pool_size: self.copy_pool_size,
+            unsent: Bytes::new(),
+            #[cfg(feature = "checksum")]
+            checksum: self.checksum.clone(),
+            #[cfg(feature = "metrics")]
+            parked: self.parked.sibling(),
+        }
+    }
 }
 
 impl<E> ByteSender<E> {
+    /// The number of bytes that may be pushed.
+    ///
+    /// If the channel's total is limited, this is at most the number of bytes that may
+    /// still be pushed before the limit is reached.
     pub fn available_window(&self) -> usize {
-        (*self.window.lock().expect("locking byte channel window")).advertised()
+        (*self.window.lock().expect("locking byte channel window")).available()
+    }
+
+    /// Fails the stream with `on_limit(LimitExceeded)` if a push would exceed the
+    /// channel's total limit. Set by `sync::new_with_limit`.
+    pub(super) fn fail_on_total_limit(&mut self, on_limit: fn(LimitExceeded) -> E) {
+        self.on_total_limit = Some(on_limit);
+    }
+
+    /// Labels the time this sender spends parked in `poll_capacity`. Set by
+    /// `Builder::metrics_label`.
+    #[cfg(feature = "metrics")]
+    pub(super) fn with_metrics_label(mut self, label: &'static str) -> Self {
+        self.parked = ParkTimer::new("byte_channel_sender_parked_seconds", label);
+        self
+    }
+
+    /// Polls for window capacity, returning the number of bytes that may be pushed.
+    ///
+    /// If no capacity is available, the current task is notified when it is. Fails if the
+    /// channel can no longer accept pushes, including once its total limit has been
+    /// reached.
+    pub fn poll_capacity(&mut self) -> Poll<usize, PushError> {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::Sending { .. }) => {}
+            Some(ChannelBuffer::LostReceiver) => return Err(PushError::LostReceiver),
+            Some(ChannelBuffer::ReceiverCancelled(_)) => return Err(PushError::Cancelled),
+            Some(ChannelBuffer::ReceiverFailed(_)) => return Err(PushError::ReceiverFailed),
+            Some(ChannelBuffer::ReceiverDone) => return Err(PushError::ReceiverDone),
+            _ => return Err(PushError::Closed),
+        }
+
+        let mut window = self.window.lock().expect("locking byte channel window");
+        if (*window).is_total_exhausted() {
+            return Err(PushError::LimitExceeded);
+        }
+        let poll = (*window).poll_advertised();
+        #[cfg(feature = "metrics")]
+        match poll {
+            Async::NotReady => self.parked.park(),
+            Async::Ready(_) => self.parked.wake(),
+        }
+        Ok(poll)
+    }
+
+    /// Fails as a push would if the channel no longer accepts pushes, without pushing.
+    pub(super) fn check_accepting(&self) -> Result<(), PushError> {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::Sending { .. }) => Ok(()),
+            Some(ChannelBuffer::LostReceiver) => Err(PushError::LostReceiver),
+            Some(ChannelBuffer::ReceiverCancelled(_)) => Err(PushError::Cancelled),
+            Some(ChannelBuffer::ReceiverFailed(_)) => Err(PushError::ReceiverFailed),
+            Some(ChannelBuffer::ReceiverDone) => Err(PushError::ReceiverDone),
+            _ => Err(PushError::Closed),
+        }
+    }
+
+    /// Polls for capacity to push the sink's bytes.
+    ///
+    /// Once the total limit has been reached, this is ready so that the push fails the
+    /// channel.
+    fn poll_sink_capacity(&mut self) -> Poll<(), PushError> {
+        match self.poll_capacity() {
+            Ok(Async::Ready(_)) | Err(PushError::LimitExceeded) => Ok(Async::Ready(())),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Indicates whether the channel's receiver has been dropped.
+    pub(super) fn is_receiver_lost(&self) -> bool {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        matches!(*buffer, Some(ChannelBuffer::LostReceiver))
+    }
+
+    /// Polls until the receiver has consumed everything pushed into the channel, i.e.
+    /// the buffer is empty and all chunks read from it have been dropped.
+    ///
+    /// Fails if the receiver has been dropped, cancelled, failed, or half-closed.
+    pub fn poll_drained(&mut self) -> Poll<(), PushError> {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::LostReceiver) | None => return Err(PushError::LostReceiver),
+            Some(ChannelBuffer::ReceiverCancelled(_)) => return Err(PushError::Cancelled),
+            Some(ChannelBuffer::ReceiverFailed(_)) => return Err(PushError::ReceiverFailed),
+            Some(ChannelBuffer::ReceiverDone) => return Err(PushError::ReceiverDone),
+            _ => {}
+        }
+
+        let mut window = self.window.lock().expect("locking byte channel window");
+        Ok((*window).poll_drained())
+    }
+
+    /// Causes `poll_capacity` to wait until at least `sz` bytes are available, so that
+    /// senders are not woken for every small amount of returned capacity.
+    ///
+    /// Less capacity is reported once the receiver has consumed everything that was
+    /// pushed, or once the channel is closed.
+    pub fn set_capacity_low_water(&self, sz: usize) {
+        (*self.window.lock().expect("locking byte channel window")).set_sender_low_water(sz);
+    }
+
+    /// The push size preferred by the receiver, e.g. its chunk alignment.
+    ///
+    /// This is advisory: producers may batch their pushes to match it so that the
+    /// receiver's reads are not held back by small segments.
+    pub fn preferred_push_size(&self) -> Option<usize> {
+        (*self.window.lock().expect("locking byte channel window")).preferred_push_size()
+    }
+
+    /// A snapshot of the channel's activity, if the channel was built to collect stats.
+    pub fn stats(&self) -> Option<ByteChannelStats> {
+        self.stats.as_ref().map(|s| s.snapshot())
+    }
+
+    /// A snapshot of the channel's flow-control state and buffer.
+    pub fn channel_stats(&self) -> ChannelStats {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        let segments = (*buffer).as_ref().map(|b| b.segment_count()).unwrap_or(0);
+        let window = self.window.lock().expect("locking byte channel window");
+        stats::channel_stats(&window, Some(segments))
     }
 
     pub fn is_empty(&self) -> bool {
@@ -32,31 +245,90 @@ impl<E> ByteSender<E> {
             .unwrap_or(0)
     }
 
+    /// The number of bytes that have been read from the channel but not yet consumed.
+    ///
+    /// These bytes are held in chunks that have not been dropped, and so their capacity
+    /// has not yet been returned to the window.
+    pub fn in_flight(&self) -> usize {
+        let buffer = self.buffer.lock().expect("locking byte channel buffer");
+        let len = (*buffer).as_ref().map(|s| s.len()).unwrap_or(0);
+        let window = self.window.lock().expect("locking byte channel window");
+        (*window).claimed().saturating_sub(len)
+    }
+
+    /// The CRC32 of all bytes pushed into the channel by any of its senders, in the order
+    /// they were buffered.
+    ///
+    /// Once the receiver has read every pushed byte, this matches
+    /// `ByteReceiver::checksum`.
+    #[cfg(feature = "checksum")]
+    pub fn checksum(&self) -> u32 {
+        self.checksum.lock().expect("locking byte channel checksum").clone().finalize()
+    }
+
+    pub(super) fn shared_window(&self) -> &SharedWindow {
+        &self.window
+    }
+
+    pub(super) fn shared_buffer(&self) -> &SharedBuffer<E> {
+        &self.buffer
+    }
+
+    /// Takes the error provided by `ByteReceiver::cancel` or `ByteReceiver::reset`, if
+    /// the receiver has cancelled or failed the stream.
+    pub fn take_cancellation(&mut self) -> Option<E> {
+        let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+        match *buffer {
+            Some(ChannelBuffer::ReceiverCancelled(ref mut e)) |
+            Some(ChannelBuffer::ReceiverFailed(ref mut e)) => e.take(),
+            _ => None,
+        }
+    }
+
     /// Causes the next receiver operation to fail with the provided error.
     pub fn reset(self, e: E) {
         let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
         return_buffer_to_window(&buffer, &self.window);
+        let awaiting_chunk = match (*buffer).take() {
+            Some(ChannelBuffer::Sending { awaiting_chunk, .. }) => awaiting_chunk,
+            _ => None,
+        };
         *buffer = Some(ChannelBuffer::SenderFailed(e));
+
+        // If the receiver is waiting for data, notify it so that it observes the error.
+        if let Some(t) = awaiting_chunk {
+            t.notify();
+        }
+    }
+
+    /// Fails the stream after the data that has already been pushed.
+    ///
+    /// The receiver reads all bytes buffered before this call and then fails with `e`.
+    /// Subsequent pushes by cloned senders are handled as pushes after close.
+    pub fn reset_after_buffered(self, e: E) {
+        let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
+        fail_after_buffered(&mut buffer, &self.window, e);
     }
 
     /// Signals that no further data will be provided.  The `ByteReceiver` may continue to
     /// read from this channel until it is empty.
+    ///
+    /// The channel is closed by the first sender that closes it; closing or dropping
+    /// other senders afterwards has no effect.
     pub fn close(mut self) {
         self.do_close();
     }
 
-    fn do_close(&mut self) {
+    pub(super) fn do_close(&mut self) {
         let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
 
         if let Some(state) = (*buffer).take() {
             match state {
                 ChannelBuffer::Sending {
-                    len,
                     buffers,
                     mut awaiting_chunk,
-                    ..
                 } => {
-                    *buffer = Some(ChannelBuffer::SenderClosed { len, buffers });
+                    *buffer = Some(ChannelBuffer::SenderClosed { buffers });
 
                     // If the receiver is waiting for data, notify it so that the channel is
                     // fully closed.
@@ -72,52 +344,361 @@ impl<E> ByteSender<E> {
         }
     }
 
-    /// Pushes bytes into the channel.
+    /// Pushes bytes into the channel, returning the number of bytes buffered in the
+    /// channel after the push.
+    ///
+    /// If another sender has closed or reset the channel, the push either fails with
+    /// `PushError::Closed` or the bytes are discarded, as configured by
+    /// `Builder::on_push_after_close`. Pushes larger than the channel's
+    /// `Builder::max_message` fail with `PushError::MessageTooLarge`.
     ///
     /// ## Panics
     ///
     /// Panics if `bytes` exceeds the advertised capacity of this channel.
+    pub fn push_bytes(&mut self, bytes: Bytes) -> Result<usize, PushError> {
+        match self.try_push_bytes(bytes) {
+            Err(PushError::Overflow { .. }) => panic!("byte channel overflow"),
+            res => res,
+        }
+    }
+
+    /// Pushes `bytes` into the channel, as `push_bytes` does, but fails with
+    /// `PushError::Overflow` rather than panicking if they exceed the advertised capacity.
+    ///
+    /// The window is checked while the channel is locked, so a failed push leaves the
+    /// channel unchanged. This allows untrusted sizes to be rejected without checking
+    /// `available_window` first.
+    pub fn try_push_bytes(&mut self, bytes: Bytes) -> Result<usize, PushError> {
+        self.push(bytes, Limit::Window).map(|(len, _)| len)
+    }
+
+    /// Converts this sender into an `AsyncWriter` that writes into the channel through
+    /// `std::io::Write`.
+    pub fn into_async_write(self) -> AsyncWriter<E> {
+        async_write::new(self)
+    }
+
+    /// Copies `src` into the channel. Errors are handled as by `push_bytes`.
+    ///
+    /// If a copy pool has been configured with `set_copy_pool_size`, slices no larger
+    /// than the pool are copied into a shared backing buffer rather than each being
+    /// allocated separately.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `src` exceeds the advertised capacity of this channel.
+    pub fn push_slice(&mut self, src: &[u8]) -> Result<usize, PushError> {
+        let bytes = if src.is_empty() || self.copy_pool_size < src.len() {
+            Bytes::from(src)
+        } else {
+            // Pushed slices keep their backing buffer alive, so the pool's storage is
+            // never reused; once it's exhausted, a new backing buffer is allocated.
+            if self.copy_pool.remaining_mut() < src.len() {
+                self.copy_pool = BytesMut::with_capacity(self.copy_pool_size);
+            }
+            self.copy_pool.put_slice(src);
+            self.copy_pool.take().freeze()
+        };
+        self.push_bytes(bytes)
+    }
+
+    /// Sets the size of the backing buffers used by `push_slice` to copy small slices.
     ///
-    /// Panics if
-    pub fn push_bytes(&mut self, bytes: Bytes) -> Result<(), LostReceiver> {
+    /// Each sender clone has its own pool. Defaults to 0, in which case each slice is
+    /// copied into a separate allocation.
+    pub fn set_copy_pool_size(&mut self, sz: usize) {
+        self.copy_pool_size = sz;
+        self.copy_pool = BytesMut::new();
+    }
+
+    /// Pushes as many bytes as the advertised capacity of this channel allows, returning
+    /// the bytes that were not pushed, which are empty if everything fit.
+    ///
+    /// Neither the pushed bytes nor the remainder are copied. Errors are handled as by
+    /// `push_up_to`.
+    pub fn push_bytes_partial(&mut self, bytes: Bytes) -> Result<Bytes, PushError> {
+        self.push_up_to(bytes, usize::MAX)
+    }
+
+    /// Pushes as many bytes as the advertised capacity of this channel allows, but no
+    /// more than `at_most`, returning the bytes that were not pushed.
+    ///
+    /// Errors are handled as by `push_bytes`. If the channel has been closed and pushes
+    /// are ignored, all of `bytes` is discarded.
+    pub fn push_up_to(&mut self, bytes: Bytes, at_most: usize) -> Result<Bytes, PushError> {
+        self.push(bytes, Limit::AtMost(at_most)).map(|(_, rest)| rest)
+    }
+
+    /// Pushes up to `max` bytes from the front of `source`, as the advertised capacity
+    /// allows, and advances `source` past them. Returns the number of bytes taken.
+    ///
+    /// The pushed bytes share `source`'s storage, so feeding a large buffer through the
+    /// channel copies nothing. Errors are handled as by `push_bytes`; if the push fails,
+    /// `source` is unchanged.
+    pub fn push_from(&mut self, source: &mut Bytes, max: usize) -> Result<usize, PushError> {
+        let rest = self.push_up_to(source.clone(), max)?;
+        let sz = source.len() - rest.len();
+        source.advance(sz);
+        Ok(sz)
+    }
+
+    /// Pushes a single byte even if no window is available, so that a peer waiting on a
+    /// zero window observes activity.
+    ///
+    /// If the window is exhausted, the byte overdraws it: the channel's next byte of
+    /// returned capacity repays the overdraft instead of being advertised.
+    pub fn probe(&mut self, byte: u8) -> Result<(), PushError> {
+        self.push(Bytes::from(vec![byte]), Limit::Probe).map(|_| ())
+    }
+
+    /// Moves up to `amount` bytes of this channel's available window to `other`'s,
+    /// returning the number of bytes moved.
+    ///
+    /// The credit is immediately available to `other`'s senders. Once it has been pushed
+    /// and consumed, it is returned to `other`'s window, so the transfer is permanent.
+    pub fn donate_window(&self, other: &ByteSender<E>, amount: usize) -> usize {
+        if Arc::ptr_eq(&self.window, &other.window) {
+            return 0;
+        }
+
+        // Windows are locked in address order so that concurrent donations in opposite
+        // directions can't deadlock.
+        let ours_first = (&*self.window as *const _ as usize) < (&*other.window as *const _ as usize);
+        let (first, second) = if ours_first {
+            (&self.window, &other.window)
+        } else {
+            (&other.window, &self.window)
+        };
+        let mut first = first.lock().expect("locking byte channel window");
+        let mut second = second.lock().expect("locking byte channel window");
+        let (from, to) = if ours_first {
+            (&mut *first, &mut *second)
+        } else {
+            (&mut *second, &mut *first)
+        };
+
+        let sz = from.reserve(amount);
+        to.release_reserved(sz);
+        sz
+    }
+
+    /// Claims up to `desired` bytes of the available window for this sender, returning
+    /// the number of bytes claimed.
+    ///
+    /// The claimed capacity may only be used by `push_reserved` and is unavailable to
+    /// other pushes until it is pushed or released with `release_reserved`.
+    pub fn try_reserve_window(&self, desired: usize) -> usize {
+        let sz = (*self.window.lock().expect("locking byte channel window")).reserve(desired);
+        self.reserved.fetch_add(sz, Ordering::SeqCst);
+        sz
+    }
+
+    /// Pushes bytes into the channel using capacity claimed by `try_reserve_window`,
+    /// returning the number of bytes buffered in the channel after the push.
+    ///
+    /// Errors are handled as by `push_bytes`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `bytes` exceeds this sender's unused reservation.
+    pub fn push_reserved(&mut self, bytes: Bytes) -> Result<usize, PushError> {
+        self.push(bytes, Limit::Reserved).map(|(len, _)| len)
+    }
+
+    /// Returns this sender's unused reservation to the window.
+    pub fn release_reserved(&self) {
+        let sz = self.reserved.swap(0, Ordering::SeqCst);
+        (*self.window.lock().expect("locking byte channel window")).release_reserved(sz);
+    }
+
+    /// Pushes the front of `bytes` into the channel, returning the number of bytes
+    /// buffered after the push and the bytes that were not pushed.
+    fn push(&mut self, mut bytes: Bytes, limit: Limit) -> Result<(usize, Bytes), PushError> {
+        if self.max_message.map(|max| max < bytes.len()).unwrap_or(false) {
+            return Err(PushError::MessageTooLarge);
+        }
+
         let mut buffer = self.buffer.lock().expect("locking byte channel buffer");
 
-        if let Some(ChannelBuffer::LostReceiver) = *buffer {
-            // If there's no receiver, drop the entire buffer and error.
-            // The receiver has already returned the buffer to the window.
-            *buffer = None;
-            return Err(LostReceiver);
-        }
-
-        if let Some(ChannelBuffer::Sending {
-                        ref mut len,
-                        ref mut awaiting_chunk,
-                        ref mut buffers,
-                        ..
-                    }) = *buffer
-        {
-            let sz = bytes.len();
-
-            let mut window = self.window.lock().expect("locking byte channel window");
-            if sz <= (*window).advertised() {
-                *len += sz;
-                (*window).claim_advertised(sz);
-                buffers.push_back(bytes);
+        match *buffer {
+            Some(ChannelBuffer::Sending {
+                     ref mut awaiting_chunk,
+                     ref mut buffers,
+                 }) => {
+                if bytes.is_empty() {
+                    return Ok((buffers.len(), bytes));
+                }
+
+                let mut window = self.window.lock().expect("locking byte channel window");
+                // Partial pushes are capped by the limit, so they fail only once nothing
+                // more may be pushed.
+                let exceeds_total = match limit {
+                    Limit::AtMost(_) => (*window).is_total_exhausted(),
+                    _ => (*window).exceeds_total(bytes.len()),
+                };
+                if exceeds_total {
+                    drop(window);
+                    if let Some(on_limit) = self.on_total_limit {
+                        fail_after_buffered(&mut buffer, &self.window, on_limit(LimitExceeded));
+                    }
+                    return Err(PushError::LimitExceeded);
+                }
+                let sz = match limit {
+                    Limit::Window if (*window).available() < bytes.len() => {
+                        return Err(PushError::Overflow {
+                            available: (*window).available(),
+                            needed: bytes.len(),
+                        });
+                    }
+                    Limit::Reserved if self.reserved.load(Ordering::SeqCst) < bytes.len() => {
+                        panic!("push exceeds reserved window")
+                    }
+                    Limit::Window | Limit::Reserved | Limit::Probe => bytes.len(),
+                    Limit::AtMost(max) => {
+                        cmp::min(bytes.len(), cmp::min(max, (*window).available()))
+                    }
+                };
+                let rest = bytes.split_off(sz);
+                if sz == 0 {
+                    return Ok((buffers.len(), rest));
+                }
+
+                match limit {
+                    Limit::Reserved => {
+                        self.reserved.fetch_sub(sz, Ordering::SeqCst);
+                        (*window).claim_reserved(sz);
+                    }
+                    Limit::Probe if (*window).advertised() < sz => (*window).overdraw(sz),
+                    _ => (*window).claim_advertised(sz),
+                }
+                (*window).count_total(sz);
+                #[cfg(feature = "record")]
+                (*window).record(::Op::Push(sz));
+                #[cfg(feature = "checksum")]
+                self.checksum.lock().expect("locking byte channel checksum").update(&bytes);
+                match self.coalesce_limit {
+                    Some(limit) => buffers.push_back_coalesced(bytes, limit),
+                    None => buffers.push_back(bytes),
+                }
+                let len = buffers.len();
+                if let Some(ref stats) = self.stats {
+                    stats.pushed(sz, len);
+                }
+                if let Some((threshold, warn)) = self.warn_buffered_above {
+                    if len - sz <= threshold && threshold < len {
+                        warn(len);
+                    }
+                }
                 if let Some(t) = awaiting_chunk.take() {
                     t.notify();
                 }
-                return Ok(());
+                Ok((len, rest))
+            }
+
+            // If there's no receiver, drop the bytes and error.  The receiver has already
+            // returned the buffer to the window.
+            Some(ChannelBuffer::LostReceiver) => Err(PushError::LostReceiver),
+
+            Some(ChannelBuffer::ReceiverCancelled(_)) => Err(PushError::Cancelled),
+
+            Some(ChannelBuffer::ReceiverFailed(_)) => Err(PushError::ReceiverFailed),
+
+            Some(ChannelBuffer::ReceiverDone) => Err(PushError::ReceiverDone),
+
+            // The bytes were never accepted, so they hold no window capacity.
+            ref state => {
+                match self.push_after_close {
+                    PushAfterClose::Error => Err(PushError::Closed),
+                    PushAfterClose::Ignore => {
+                        let len = state.as_ref().map(|s| s.len()).unwrap_or(0);
+                        Ok((len, Bytes::new()))
+                    }
+                }
             }
+        }
+    }
+}
 
-            panic!("byte channel overflow");
+/// Fails the stream once the receiver has read the bytes that are already buffered.
+fn fail_after_buffered<E>(buffer: &mut Option<ChannelBuffer<E>>, window: &SharedWindow, e: E) {
+    let (buffers, awaiting_chunk) = match buffer.take() {
+        Some(ChannelBuffer::Sending { buffers, awaiting_chunk }) => (buffers, awaiting_chunk),
+        Some(ChannelBuffer::SenderClosed { buffers }) => (buffers, None),
+        state => {
+            return_buffer_to_window(&state, window);
+            (Rope::new(), None)
         }
+    };
+
+    *buffer = Some(if buffers.is_empty() {
+        ChannelBuffer::SenderFailed(e)
+    } else {
+        ChannelBuffer::DrainingThenFailed { buffers, error: e }
+    });
 
-        panic!("ByteSender::push called in illegal buffer state");
+    if let Some(t) = awaiting_chunk {
+        t.notify();
+    }
+}
+
+/// Determines how much of a push is accepted.
+#[derive(Copy, Clone, Debug)]
+enum Limit {
+    /// All of the bytes must fit in the advertised window.
+    Window,
+
+    /// As many bytes as the advertised window allows, up to a maximum.
+    AtMost(usize),
+
+    /// All of the bytes must fit in the sender's reservation.
+    Reserved,
+
+    /// The bytes may overdraw an exhausted window.
+    Probe,
+}
+
+/// Pushes bytes as the window allows.
+///
+/// Each item is accepted once the previous item has been pushed entirely and the window
+/// has room for some of it, so items larger than the window are pushed across several
+/// polls rather than overflowing it. Bytes that
+/// have been accepted but not yet pushed are discarded if the sender is dropped before
+/// `poll_complete` succeeds. `close` flushes the sender and then closes the channel.
+impl<E> Sink for ByteSender<E> {
+    type SinkItem = Bytes;
+    type SinkError = PushError;
+
+    fn start_send(&mut self, item: Bytes) -> StartSend<Bytes, PushError> {
+        if self.poll_complete()?.is_not_ready() || self.poll_sink_capacity()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        self.unsent = self.push_bytes_partial(item)?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), PushError> {
+        while !self.unsent.is_empty() {
+            try_ready!(self.poll_sink_capacity());
+            let unsent = ::std::mem::replace(&mut self.unsent, Bytes::new());
+            self.unsent = self.push_bytes_partial(unsent)?;
+        }
+        Ok(Async::Ready(()))
+    }
+
+    fn close(&mut self) -> Poll<(), PushError> {
+        try_ready!(self.poll_complete());
+        self.do_close();
+        Ok(Async::Ready(()))
     }
 }
 
 impl<E> Drop for ByteSender<E> {
     fn drop(&mut self) {
-        self.do_close();
+        if let Ok(mut window) = self.window.lock() {
+            (*window).release_reserved(*self.reserved.get_mut());
+        }
+        if self.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.do_close();
+        }
     }
 }
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use window::Window;
+
+/// Activity counters shared by a channel's handles and chunks.
+///
+/// Channels that don't collect stats hold `None` rather than an empty set of counters.
+pub type SharedStats = Option<Arc<Stats>>;
+
+/// A snapshot of a channel's activity, as returned by `ByteSender::stats` and
+/// `ByteReceiver::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ByteChannelStats {
+    /// The number of bytes pushed into the channel.
+    pub bytes_pushed: usize,
+
+    /// The number of bytes consumed by the receiver.
+    pub bytes_consumed: usize,
+
+    /// The number of times the receiver waited for data.
+    pub blocked: usize,
+
+    /// The largest number of bytes buffered in the channel at once.
+    pub high_water: usize,
+
+    /// The number of chunks that have been read but not yet consumed.
+    pub outstanding_chunks: usize,
+}
+
+/// A snapshot of a channel's flow-control state, as returned by `channel_stats`, e.g. for
+/// exporting gauges.
+///
+/// The window size is `advertised + pending + buffered - underflow`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// The window currently available to the channel's senders.
+    pub advertised: usize,
+
+    /// Capacity that has been returned by the receiver but not yet advertised.
+    pub pending: usize,
+
+    /// Capacity that must be returned before the window grows, after it was shrunk.
+    pub underflow: usize,
+
+    /// The number of bytes pushed but not yet consumed, including bytes held by chunks
+    /// that have been read.
+    pub buffered: usize,
+
+    /// The number of segments buffered in the channel.
+    ///
+    /// Only a channel's sender and receiver hold its buffer, so this is `None` when
+    /// taken by a `WindowAdvertiser`.
+    pub segments: Option<usize>,
+}
+
+pub fn channel_stats(window: &Window, segments: Option<usize>) -> ChannelStats {
+    let snapshot = window.snapshot();
+    ChannelStats {
+        advertised: snapshot.advertised,
+        pending: snapshot.pending,
+        underflow: snapshot.underflow,
+        buffered: window.claimed(),
+        segments,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    bytes_pushed: AtomicUsize,
+    bytes_consumed: AtomicUsize,
+    blocked: AtomicUsize,
+    high_water: AtomicUsize,
+    outstanding_chunks: AtomicUsize,
+}
+
+impl Stats {
+    pub fn snapshot(&self) -> ByteChannelStats {
+        ByteChannelStats {
+            bytes_pushed: self.bytes_pushed.load(Ordering::Relaxed),
+            bytes_consumed: self.bytes_consumed.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+            high_water: self.high_water.load(Ordering::Relaxed),
+            outstanding_chunks: self.outstanding_chunks.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Records a push of `sz` bytes, after which `buffered` bytes are buffered.
+    pub fn pushed(&self, sz: usize, buffered: usize) {
+        self.bytes_pushed.fetch_add(sz, Ordering::Relaxed);
+        self.high_water.fetch_max(buffered, Ordering::Relaxed);
+    }
+
+    pub fn consumed(&self, sz: usize) {
+        self.bytes_consumed.fetch_add(sz, Ordering::Relaxed);
+    }
+
+    pub fn blocked(&self) {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn chunk_created(&self) {
+        self.outstanding_chunks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn chunk_dropped(&self) {
+        self.outstanding_chunks.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,62 @@
+use futures::*;
+use std::time::Duration;
+
+use TimedOut;
+use super::{ByteReceiver, Chunk, Timer};
+
+pub fn new<'a, E, T: Timer>(
+    receiver: &'a mut ByteReceiver<E>,
+    max_sz: usize,
+    timeout: Duration,
+    timer: &T,
+) -> RecvTimeout<'a, E, T> {
+    let delay = timer.delay_until(timer.now() + timeout);
+    RecvTimeout {
+        receiver,
+        max_sz,
+        delay: Some(delay),
+    }
+}
+
+/// Reads a chunk of at most `max_sz` bytes, failing with `TimedOut` if no data is ready
+/// before the timeout elapses.
+///
+/// While waiting, the receiver's task is registered to be notified by the sender, as by
+/// `poll_chunk`. When the timeout fires, that registration is cleared, so that a later
+/// push does not notify a task that is no longer waiting on the channel. When data
+/// arrives first, the timer's delay is dropped.
+///
+/// A delay that fails is treated as having elapsed.
+///
+/// Created by `ByteReceiver::recv_timeout`.
+#[derive(Debug)]
+pub struct RecvTimeout<'a, E: 'a, T: Timer> {
+    receiver: &'a mut ByteReceiver<E>,
+    max_sz: usize,
+    delay: Option<T::Delay>,
+}
+
+impl<'a, E, T> Future for RecvTimeout<'a, E, T>
+where
+    E: From<TimedOut>,
+    T: Timer,
+{
+    type Item = Option<Chunk>;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, E> {
+        let delay = self.delay.as_mut().expect("RecvTimeout polled after completion");
+        if let Async::Ready(chunk) = self.receiver.poll_chunk(self.max_sz)? {
+            self.delay = None;
+            return Ok(Async::Ready(chunk));
+        }
+
+        if let Ok(Async::NotReady) = delay.poll() {
+            return Ok(Async::NotReady);
+        }
+
+        self.delay = None;
+        self.receiver.unpark();
+        Err(TimedOut.into())
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use Deadlock;
+use buffer::ChannelBuffer;
+use super::{ByteReceiver, ByteSender, WindowAdvertiser, WindowObserver};
+
+/// Detects a channel whose receiver and sender are parked waiting on each other.
+///
+/// This happens when the receiver waits for data while the sender waits for window, and
+/// the credit that would unblock the sender is not published, e.g. because the
+/// advertiser is never polled. Such a channel makes no progress, but nothing fails.
+///
+/// A sender that waits for window while returned credit sits unpublished is flagged
+/// too, even if the receiver isn't waiting, e.g. because it holds a partially consumed
+/// chunk while the advertiser is parked below its notify threshold.
+///
+/// The watchdog does not keep the channel alive.
+#[derive(Debug)]
+pub struct Watchdog<E> {
+    buffer: Weak<Mutex<Option<ChannelBuffer<E>>>>,
+    window: WindowObserver,
+    parked_at: Option<Instant>,
+}
+
+impl<E> Watchdog<E> {
+    /// Watches the channel shared by `advertiser`, `sender`, and `receiver`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the handles belong to different channels.
+    pub fn new(
+        advertiser: &WindowAdvertiser,
+        sender: &ByteSender<E>,
+        receiver: &ByteReceiver<E>,
+    ) -> Self {
+        let window = sender.shared_window();
+        assert!(
+            Arc::ptr_eq(window, advertiser.shared_window()) &&
+                Arc::ptr_eq(window, receiver.shared_window()),
+            "watchdog handles must belong to one channel"
+        );
+        Watchdog {
+            buffer: Arc::downgrade(sender.shared_buffer()),
+            window: receiver.window_observer(),
+            parked_at: None,
+        }
+    }
+
+    /// Fails if the sender is parked waiting for window that the receiver or the
+    /// advertiser will not provide.
+    ///
+    /// The time at which this was first observed is recorded, and the error reports how
+    /// long the channel has been parked since. A supervisor should call this
+    /// periodically, since the state may be observed briefly while a wakeup is in
+    /// flight; a state that persists across checks indicates a deadlock.
+    pub fn check_deadlock(&mut self) -> Result<(), Deadlock> {
+        if !self.is_parked() {
+            self.parked_at = None;
+            return Ok(());
+        }
+
+        let parked_at = *self.parked_at.get_or_insert_with(Instant::now);
+        Err(Deadlock {
+            parked_for: parked_at.elapsed(),
+        })
+    }
+
+    fn is_parked(&self) -> bool {
+        let buffer = match self.buffer.upgrade() {
+            Some(b) => b,
+            None => return false,
+        };
+
+        // The buffer is locked before the window, as elsewhere.
+        let buffer = buffer.lock().expect("locking byte channel buffer");
+        let receiver_parked = buffer.as_ref().map(|b| b.is_awaiting_chunk()).unwrap_or(false);
+        self.window
+            .with_window(|window| {
+                let window = window.lock().expect("locking byte channel window");
+                let unpublished = 0 < window.snapshot().pending;
+                window.is_awaiting_capacity() && (receiver_parked || unpublished)
+            })
+            .unwrap_or(false)
+    }
+}
@@ -2,36 +2,117 @@ use futures::*;
 use std::sync::Arc;
 
 use super::SharedWindow;
+#[cfg(feature = "futures03")]
+use super::compat::NotifyCache;
+use super::stats::{self, ChannelStats};
+#[cfg(feature = "metrics")]
+use super::latency::ParkTimer;
+#[cfg(feature = "timer")]
+use super::paced::{self, Paced, Timer};
+use window::WindowSnapshot;
 
+#[cfg(not(feature = "metrics"))]
 pub fn new(w: SharedWindow) -> WindowAdvertiser {
-    WindowAdvertiser(w)
+    WindowAdvertiser {
+        window: w,
+        #[cfg(feature = "futures03")]
+        notify: NotifyCache::default(),
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub fn new(w: SharedWindow, label: &'static str) -> WindowAdvertiser {
+    WindowAdvertiser {
+        window: w,
+        parked: ParkTimer::new("byte_channel_window_parked_seconds", label),
+        #[cfg(feature = "futures03")]
+        notify: NotifyCache::default(),
+    }
 }
 
 /// Publishes window increments on the channel.
 #[derive(Debug)]
-pub struct WindowAdvertiser(SharedWindow);
+pub struct WindowAdvertiser {
+    window: SharedWindow,
+    #[cfg(feature = "metrics")]
+    parked: ParkTimer,
+    #[cfg(feature = "futures03")]
+    pub(super) notify: NotifyCache,
+}
 
 impl WindowAdvertiser {
-    fn is_orphaned(&self) -> bool {
-        // `BytesSender` and `BytesReceiver` each retain a strong reference to the window.
-        // Each `Chunk` produced by `ByteReceiver` retains a weak reference.
-        Arc::strong_count(&self.0) == 1 && Arc::weak_count(&self.0) == 0
+    /// Limits the size of each increment published by this advertiser.
+    ///
+    /// Larger increments are published across several polls.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn with_max_advertisement(self, max: usize) -> Self {
+        (*self.window.lock().expect("locking byte channel window")).set_max_increment(max);
+        self
     }
-}
 
-impl Stream for WindowAdvertiser {
-    type Item = usize;
-    type Error = ();
+    /// Captures the channel's current flow-control state.
+    pub fn snapshot(&self) -> WindowSnapshot {
+        (*self.window.lock().expect("locking byte channel window")).snapshot()
+    }
 
-    fn poll(&mut self) -> Poll<Option<usize>, ()> {
+    /// A snapshot of the channel's flow-control state.
+    ///
+    /// The advertiser doesn't hold the channel's buffer, so the snapshot's `segments` is
+    /// `None`.
+    pub fn channel_stats(&self) -> ChannelStats {
+        stats::channel_stats(&self.window.lock().expect("locking byte channel window"), None)
+    }
+
+    /// Replaces the channel's flow-control state, e.g. to resume a stream after
+    /// reconnecting.
+    pub fn restore(&self, snapshot: WindowSnapshot) {
+        (*self.window.lock().expect("locking byte channel window")).restore(snapshot);
+    }
+
+    /// Offers the sender `sz` bytes of additional capacity, as `ByteReceiver::grow_window`
+    /// does, so that a component holding only the advertiser may open the window.
+    ///
+    /// The increment is published by subsequent polls of this advertiser.
+    pub fn advertise(&self, sz: usize) {
+        let mut window = self.window.lock().expect("locking byte channel window");
+        (*window).advertise_increment(sz);
+        #[cfg(feature = "record")]
+        (*window).record(::Op::Grow(sz));
+    }
+
+    /// Releases credit at no more than `rate` bytes per second, as measured by `timer`.
+    #[cfg(feature = "timer")]
+    pub fn paced<T: Timer>(self, rate: usize, timer: T) -> Paced<T> {
+        paced::new(self, rate, timer)
+    }
+
+    /// Publishes increments as soon as capacity is returned, without this advertiser
+    /// being polled.
+    pub(super) fn into_automatic(self) {
+        (*self.window.lock().expect("locking byte channel window")).set_automatic();
+    }
+
+    /// Polls for the next increment, publishing at most `max` bytes.
+    pub(super) fn poll_max(&mut self, max: usize) -> Poll<Option<usize>, ()> {
         // If the window isn't closed, return either a new increment or indicate that
         // an increment isn't ready.  When poll_increment is not ready, it saves the
         // task to be notified by a channel.
-        match (*self.0.lock().expect("locking byte channel"))
-            .poll_increment()? {
-            Async::Ready(incr) => Ok(Async::Ready(Some(incr))),
+        let poll = (*self.window.lock().expect("locking byte channel")).poll_increment(max);
+        #[cfg(feature = "metrics")]
+        match poll {
+            Ok(Async::NotReady) => self.parked.park(),
+            _ => self.parked.wake(),
+        }
+        match poll {
+            Ok(Async::Ready(incr)) => Ok(Async::Ready(Some(incr))),
+
+            // The window is closed once the channel's receiver has been dropped.
+            Err(()) => Ok(Async::Ready(None)),
 
-            Async::NotReady => {
+            Ok(Async::NotReady) => {
                 if self.is_orphaned() {
                     Ok(Async::Ready(None))
                 } else {
@@ -40,4 +121,157 @@ impl Stream for WindowAdvertiser {
             }
         }
     }
+
+    /// The strong and weak reference counts of the channel's window.
+    #[cfg(test)]
+    pub(crate) fn window_refs(&self) -> (usize, usize) {
+        (Arc::strong_count(&self.window), Arc::weak_count(&self.window))
+    }
+
+    pub(super) fn shared_window(&self) -> &SharedWindow {
+        &self.window
+    }
+
+    fn is_orphaned(&self) -> bool {
+        // `BytesSender` and `BytesReceiver` each retain a strong reference to the window.
+        // Each `Chunk` produced by `ByteReceiver` retains a weak reference, as does each
+        // `WindowObserver`, which doesn't keep the channel alive.
+        let observers = (*self.window.lock().expect("locking byte channel window")).observers();
+        Arc::strong_count(&self.window) == 1 && Arc::weak_count(&self.window) == observers
+    }
+}
+
+impl Stream for WindowAdvertiser {
+    type Item = usize;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<usize>, ()> {
+        self.poll_max(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{Buf, Bytes};
+    use futures::*;
+    use futures::executor::{Notify, NotifyHandle};
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use test_futures::*;
+
+    use window::Window;
+    use super::super::{new, observer};
+
+    struct Counter(AtomicUsize);
+
+    impl Notify for Counter {
+        fn notify(&self, _id: usize) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn window_refs_track_handles_and_chunks() {
+        let (mut wx, mut tx, mut rx) = new::<()>(8);
+        assert_eq!(wx.window_refs(), (3, 0));
+        assert_eq!(rx.buffer_refs(), (2, 0));
+        sassert_next(&mut wx, 8);
+
+        tx.push_bytes(Bytes::from("0123")).unwrap();
+        assert_eq!(rx.window_refs(), (3, 0));
+
+        let chunk = {
+            let mut task = executor::spawn(future::poll_fn(|| rx.poll_chunk(8)));
+            match task.poll_future_notify(&notify_panic(), 0) {
+                Ok(Async::Ready(Some(chunk))) => chunk,
+                res => panic!("expected chunk: {:?}", res),
+            }
+        };
+        assert_eq!(wx.window_refs(), (3, 1));
+
+        drop(tx);
+        assert_eq!(rx.buffer_refs(), (1, 0));
+        assert_eq!(wx.window_refs(), (2, 1));
+
+        drop(rx);
+        assert_eq!(wx.window_refs(), (1, 1));
+        assert!(!wx.is_orphaned());
+
+        drop(chunk);
+        assert_eq!(wx.window_refs(), (1, 0));
+        assert!(wx.is_orphaned());
+    }
+
+    #[test]
+    fn window_tracks_claims_and_returns() {
+        let (mut wx, mut tx, mut rx) = new::<()>(8);
+        sassert_next(&mut wx, 8);
+        assert_eq!(wx.snapshot().advertised, 8);
+
+        // Pushing claims advertised capacity.
+        tx.push_bytes(Bytes::from("012345")).unwrap();
+        assert_eq!(wx.snapshot().advertised, 2);
+        assert_eq!(tx.available_window(), 2);
+
+        // Reading holds the claim until the chunk is consumed.
+        let mut chunk = {
+            let mut task = executor::spawn(future::poll_fn(|| rx.poll_chunk(4)));
+            match task.poll_future_notify(&notify_panic(), 0) {
+                Ok(Async::Ready(Some(chunk))) => chunk,
+                res => panic!("expected chunk: {:?}", res),
+            }
+        };
+        sassert_empty(&mut wx);
+        assert_eq!(wx.snapshot().pending, 0);
+
+        // Consumed capacity is pending until the advertiser publishes it.
+        chunk.advance(3);
+        assert_eq!(wx.snapshot().pending, 3);
+        assert_eq!(tx.available_window(), 2);
+        sassert_next(&mut wx, 3);
+        assert_eq!(tx.available_window(), 5);
+
+        drop(chunk);
+        rx.grow_window(1);
+        sassert_next(&mut wx, 2);
+        assert_eq!(wx.snapshot().advertised, 7);
+        assert_eq!(tx.available_window() + tx.len(), 8 + 1);
+    }
+
+    #[test]
+    fn observers_do_not_prevent_orphaning() {
+        let (wx, tx, rx) = new::<()>(8);
+        let observer = rx.window_observer();
+        let observer2 = observer.clone();
+        assert_eq!(wx.window_refs(), (3, 2));
+
+        drop((tx, rx));
+        assert!(wx.is_orphaned());
+        drop(observer);
+        assert!(wx.is_orphaned());
+        drop(observer2);
+        assert_eq!(wx.window_refs(), (1, 0));
+        assert!(wx.is_orphaned());
+    }
+
+    #[test]
+    fn observer_reads_do_not_hide_orphaning() {
+        let window = Arc::new(Mutex::new(Window::new(0)));
+        #[cfg(not(feature = "metrics"))]
+        let wx = super::new(window.clone());
+        #[cfg(feature = "metrics")]
+        let wx = super::new(window.clone(), "test");
+        let observer = observer::new(&window, None);
+        drop(window);
+        let mut wx = executor::spawn(wx);
+
+        // The advertiser parks while the observer holds a reference to the window, and
+        // is notified once the reference is released.
+        let count = Arc::new(Counter(AtomicUsize::new(0)));
+        let notify = NotifyHandle::from(count.clone());
+        let polled = observer.with_window(|_| wx.poll_stream_notify(&notify, 0));
+        assert_eq!(polled, Some(Ok(Async::NotReady)));
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(wx.poll_stream_notify(&notify_panic(), 0), Ok(Async::Ready(None)));
+    }
 }
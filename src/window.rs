@@ -1,4 +1,7 @@
 use futures::*;
+use futures::task::AtomicTask;
+use std::cmp;
+use std::sync::Arc;
 
 /// Tracks window sizes.
 #[derive(Debug)]
@@ -6,7 +9,97 @@ pub struct Window {
     pending_increment: usize,
     advertised: usize,
     underflow: usize,
+    claimed: usize,
+    burst: usize,
+    max_increment: usize,
+    closed: bool,
+    automatic: bool,
+    preferred_push_size: Option<usize>,
+    sender_low_water: usize,
+    /// The pending increment at which a blocked advertiser is notified.
+    notify_threshold: usize,
+    /// The number of bytes that may still be pushed, if the channel's total is limited.
+    remaining_total: Option<usize>,
+    observers: usize,
+    /// Notifies the advertiser when an observer releases a reference to the window, so
+    /// that it may check again whether the window is orphaned.
+    observed: Arc<AtomicTask>,
     blocked: Option<task::Task>,
+    awaiting_capacity: Option<task::Task>,
+    awaiting_drain: Option<task::Task>,
+    #[cfg(feature = "record")]
+    recorded: Vec<Recorded>,
+}
+
+/// Determines how much capacity must be returned to a window before a blocked
+/// `WindowAdvertiser` is notified.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NotifyThreshold {
+    /// Notify once at least this many bytes are pending.
+    Bytes(usize),
+
+    /// Notify once the pending bytes are at least this fraction of the window size.
+    Ratio(f64),
+}
+
+impl NotifyThreshold {
+    /// The number of pending bytes this threshold requires for a window of
+    /// `window_size` bytes.
+    ///
+    /// The threshold is never less than 1 byte nor more than the window size. Since
+    /// returned capacity may still fall short of it, e.g. after the window shrinks or
+    /// while chunks are held, a blocked advertiser is also woken below the threshold once
+    /// all of the window's capacity is pending, or while a sender waits for capacity.
+    pub fn bytes(&self, window_size: usize) -> usize {
+        let sz = match *self {
+            NotifyThreshold::Bytes(sz) => sz,
+            NotifyThreshold::Ratio(r) => (window_size as f64 * r).ceil() as usize,
+        };
+        cmp::max(1, cmp::min(sz, window_size))
+    }
+}
+
+/// A copy of a window's flow-control state.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowSnapshot {
+    pub advertised: usize,
+    pub pending: usize,
+    pub underflow: usize,
+}
+
+/// An operation on a channel, as recorded by the `record` feature.
+#[cfg(feature = "record")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// The channel was created.
+    Open { window_size: usize, burst_size: usize },
+
+    /// Bytes were pushed.
+    Push(usize),
+
+    /// The receiver read a chunk of `len` bytes, if any, with the given `max_sz`.
+    PollChunk { max_sz: usize, len: Option<usize> },
+
+    /// Capacity was returned to the window, e.g. by advancing or dropping a chunk.
+    Advance(usize),
+
+    /// The receiver shrank the window.
+    Shrink(usize),
+
+    /// The receiver grew the window.
+    Grow(usize),
+
+    /// The advertiser published an increment.
+    Increment(usize),
+}
+
+/// An operation and the window's state immediately after it.
+#[cfg(feature = "record")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Recorded {
+    pub op: Op,
+    pub window: WindowSnapshot,
+    pub claimed: usize,
 }
 
 impl Window {
@@ -15,14 +108,281 @@ impl Window {
             pending_increment,
             advertised: 0,
             underflow: 0,
+            claimed: 0,
+            burst: 0,
+            max_increment: usize::MAX,
+            closed: false,
+            automatic: false,
+            preferred_push_size: None,
+            sender_low_water: 1,
+            notify_threshold: 1,
+            remaining_total: None,
+            observers: 0,
+            observed: Arc::new(AtomicTask::new()),
             blocked: None,
+            awaiting_capacity: None,
+            awaiting_drain: None,
+            #[cfg(feature = "record")]
+            recorded: vec![Recorded {
+                op: Op::Open {
+                    window_size: pending_increment,
+                    burst_size: pending_increment,
+                },
+                window: WindowSnapshot {
+                    pending: pending_increment,
+                    ..WindowSnapshot::default()
+                },
+                claimed: 0,
+            }],
         }
     }
 
+    /// Creates a window that initially advertises `burst` bytes but settles at `steady`
+    /// bytes once the first `burst - steady` bytes have been consumed.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `burst` is smaller than `steady`.
+    pub fn new_with_burst(steady: usize, burst: usize) -> Window {
+        assert!(steady <= burst, "burst window must not be smaller than steady window");
+        let mut win = Window::new(burst);
+        win.burst = burst - steady;
+        #[cfg(feature = "record")]
+        {
+            win.recorded[0].op = Op::Open {
+                window_size: steady,
+                burst_size: burst,
+            };
+        }
+        win
+    }
+
+    /// Creates a window, as by `new_with_burst`, that only notifies a blocked advertiser
+    /// once the pending increment reaches `threshold`.
+    pub fn with_threshold(steady: usize, burst: usize, threshold: NotifyThreshold) -> Window {
+        let mut win = Window::new_with_burst(steady, burst);
+        win.notify_threshold = threshold.bytes(steady);
+        win
+    }
+
+    /// Records an operation along with the window's current state.
+    #[cfg(feature = "record")]
+    pub fn record(&mut self, op: Op) {
+        let recorded = Recorded {
+            op,
+            window: self.snapshot(),
+            claimed: self.claimed,
+        };
+        self.recorded.push(recorded);
+    }
+
+    #[cfg(feature = "record")]
+    pub fn recorded(&self) -> &[Recorded] {
+        &self.recorded
+    }
+
     pub fn advertised(&self) -> usize {
         self.advertised
     }
 
+    /// The advertised capacity, limited by the bytes that may still be pushed if the
+    /// channel's total is limited.
+    pub fn available(&self) -> usize {
+        match self.remaining_total {
+            Some(remaining) => cmp::min(self.advertised, remaining),
+            None => self.advertised,
+        }
+    }
+
+    /// Limits the total number of bytes that may be pushed into the channel.
+    pub fn set_total_limit(&mut self, limit: usize) {
+        self.remaining_total = Some(limit);
+    }
+
+    /// Whether pushing `sz` more bytes would exceed the channel's total limit.
+    pub fn exceeds_total(&self, sz: usize) -> bool {
+        self.remaining_total.map(|remaining| remaining < sz).unwrap_or(false)
+    }
+
+    /// Whether the channel's total limit has been reached, so that nothing more may be
+    /// pushed.
+    pub fn is_total_exhausted(&self) -> bool {
+        self.remaining_total == Some(0)
+    }
+
+    /// Counts pushed bytes against the channel's total limit.
+    pub fn count_total(&mut self, sz: usize) {
+        if let Some(ref mut remaining) = self.remaining_total {
+            *remaining -= sz;
+        }
+    }
+
+    /// The number of claimed bytes that have not yet been returned to the window.
+    pub fn claimed(&self) -> usize {
+        self.claimed
+    }
+
+    /// The push size requested by the receiver, if any.
+    pub fn preferred_push_size(&self) -> Option<usize> {
+        self.preferred_push_size
+    }
+
+    pub fn set_preferred_push_size(&mut self, sz: Option<usize>) {
+        self.preferred_push_size = sz;
+    }
+
+    /// The number of weak references to the window held by observers rather than by
+    /// chunks.
+    pub fn observers(&self) -> usize {
+        self.observers
+    }
+
+    pub fn add_observer(&mut self) {
+        self.observers += 1;
+    }
+
+    pub fn remove_observer(&mut self) {
+        self.observers -= 1;
+    }
+
+    /// The task notified by observers once they release a reference to the window.
+    pub fn observed_task(&self) -> Arc<AtomicTask> {
+        self.observed.clone()
+    }
+
+    pub fn snapshot(&self) -> WindowSnapshot {
+        WindowSnapshot {
+            advertised: self.advertised,
+            pending: self.pending_increment,
+            underflow: self.underflow,
+        }
+    }
+
+    /// Replaces the window's state with a snapshot.
+    ///
+    /// A blocked task is notified so that it observes the restored state.
+    pub fn restore(&mut self, snapshot: WindowSnapshot) {
+        self.advertised = snapshot.advertised;
+        self.pending_increment = snapshot.pending;
+        self.underflow = snapshot.underflow;
+        if let Some(t) = self.blocked.take() {
+            t.notify();
+        }
+        if let Some(t) = self.awaiting_capacity.take() {
+            t.notify();
+        }
+    }
+
+    /// Applies increments as soon as they are saved, so that the window does not rely on
+    /// `poll_increment` being called.
+    pub fn set_automatic(&mut self) {
+        self.automatic = true;
+        self.credit_now();
+    }
+
+    /// Applies all pending increments immediately, returning the number of bytes added to
+    /// the advertised window.
+    ///
+    /// Unlike `poll_increment`, no task is saved when nothing is pending.
+    pub fn credit_now(&mut self) -> usize {
+        let mut credited = 0;
+        while let Some(incr) = self.apply_increment(usize::MAX) {
+            credited += incr;
+        }
+        credited
+    }
+
+    /// Returns the available capacity. If too little is available, the current task is
+    /// saved to be notified when enough capacity is advertised or the window is closed.
+    pub fn poll_advertised(&mut self) -> Async<usize> {
+        if self.capacity_ready() || self.closed {
+            return Async::Ready(self.available());
+        }
+        self.awaiting_capacity = Some(task::current());
+        // Capacity held back by the notify threshold is needed now.
+        if 0 < self.pending_increment {
+            if let Some(t) = self.blocked.take() {
+                t.notify();
+            }
+        }
+        Async::NotReady
+    }
+
+    /// Ready once all claimed capacity has been returned, i.e. the receiver has consumed
+    /// every pushed byte, or once the window has been closed. Otherwise, the current task
+    /// is saved to be notified when it is.
+    pub fn poll_drained(&mut self) -> Async<()> {
+        if self.claimed == 0 || self.closed {
+            return Async::Ready(());
+        }
+        self.awaiting_drain = Some(task::current());
+        Async::NotReady
+    }
+
+    /// Indicates whether a sender is parked in `poll_advertised`.
+    pub fn is_awaiting_capacity(&self) -> bool {
+        self.awaiting_capacity.is_some()
+    }
+
+    /// Causes a sender waiting in `poll_advertised` to be notified only once at least
+    /// `sz` bytes are available.
+    ///
+    /// A smaller amount is still reported once all claimed capacity has been returned,
+    /// since no further capacity can be returned until the sender pushes, or once it is
+    /// all that the total limit allows.
+    pub fn set_sender_low_water(&mut self, sz: usize) {
+        self.sender_low_water = cmp::max(sz, 1);
+        self.notify_capacity();
+    }
+
+    fn capacity_ready(&self) -> bool {
+        let available = self.available();
+        self.sender_low_water <= available ||
+            (0 < available && (self.claimed == 0 || self.remaining_total == Some(available)))
+    }
+
+    /// Notifies a sender waiting for capacity, if enough is available.
+    fn notify_capacity(&mut self) {
+        if self.capacity_ready() {
+            if let Some(t) = self.awaiting_capacity.take() {
+                t.notify();
+            }
+        }
+    }
+
+    /// Limits the size of every increment returned by `poll_increment`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn set_max_increment(&mut self, max: usize) {
+        assert!(0 < max, "max increment must be non-zero");
+        self.max_increment = max;
+    }
+
+    /// Marks the window as closed, e.g. because the channel's receiver has been dropped.
+    ///
+    /// Once closed, `poll_increment` fails and a blocked task is notified so that it
+    /// observes the failure.
+    pub fn close(&mut self) {
+        self.closed = true;
+        if let Some(t) = self.blocked.take() {
+            t.notify();
+        }
+        self.wake_senders();
+    }
+
+    /// Notifies senders waiting for capacity or for the channel to drain, e.g. so that
+    /// they observe that the receiver has stopped reading.
+    pub fn wake_senders(&mut self) {
+        if let Some(t) = self.awaiting_capacity.take() {
+            t.notify();
+        }
+        if let Some(t) = self.awaiting_drain.take() {
+            t.notify();
+        }
+    }
+
     /// Saves a window increment to be applied when `poll_increment` is called.
     pub fn advertise_increment(&mut self, incr: usize) {
         if incr == 0 {
@@ -43,22 +403,63 @@ impl Window {
         self.pending_increment += incr;
         debug_assert!(0 < incr);
 
-        // TODO be more discrening about notifaction.  (Ensure some ratio between
-        // available and pending or ...)
-        if let Some(t) = self.blocked.take() {
-            t.notify();
+        if self.automatic {
+            self.credit_now();
+            return;
+        }
+
+        // Smaller increments accumulate until the threshold is reached, so that
+        // fine-grained consumption doesn't wake the advertiser for each byte.
+        if self.notify_threshold <= self.pending_increment || self.bypasses_threshold() {
+            if let Some(t) = self.blocked.take() {
+                t.notify();
+            }
         }
     }
 
-    /// Obtains and applies the next window increment.
+    /// Indicates whether pending capacity must be published although it's below the
+    /// notify threshold: either all of the window's capacity is pending, so no more will
+    /// be returned, or a sender is waiting for it.
+    fn bypasses_threshold(&self) -> bool {
+        self.advertised + self.claimed == 0 || self.awaiting_capacity.is_some()
+    }
+
+    /// Returns previously-claimed capacity to the window.
+    ///
+    /// Returned capacity is withheld until any initial burst allowance has been spent.
+    pub fn return_claimed(&mut self, sz: usize) {
+        self.claimed = self.claimed.saturating_sub(sz);
+        if self.claimed == 0 {
+            if let Some(t) = self.awaiting_drain.take() {
+                t.notify();
+            }
+        }
+        let withheld = cmp::min(sz, self.burst);
+        self.burst -= withheld;
+        self.advertise_increment(sz - withheld);
+        #[cfg(feature = "record")]
+        self.record(Op::Advance(sz));
+    }
+
+    /// Obtains and applies the next window increment, applying at most `max` bytes.
     ///
     /// If no increment is available, the current task is saved to be notified when the
     /// window is open.
-    pub fn poll_increment(&mut self) -> Poll<usize, ()> {
-        Ok(match self.apply_increment() {
-            Some(incr) => Async::Ready(incr),
+    ///
+    /// Once the window has been closed, any pending increment (e.g. the credit returned
+    /// by a dropped receiver) is still applied, and then polls fail, since no further
+    /// increments can be used.
+    pub fn poll_increment(&mut self, max: usize) -> Poll<usize, ()> {
+        Ok(match self.apply_increment(max) {
+            Some(incr) => {
+                #[cfg(feature = "record")]
+                self.record(Op::Increment(incr));
+                Async::Ready(incr)
+            }
+            None if self.closed => return Err(()),
             None => {
                 self.blocked = Some(task::current());
+                self.observed.register();
                 Async::NotReady
             }
         })
@@ -66,7 +467,9 @@ impl Window {
 
     /// If a non-zero increment is pending, apply it to the window and return the amount
     /// of available space added.
-    fn apply_increment(&mut self) -> Option<usize> {
+    ///
+    /// At most `max` (and `max_increment`) bytes are applied; any remainder stays pending.
+    fn apply_increment(&mut self, max: usize) -> Option<usize> {
         if self.pending_increment == 0 {
             return None;
         }
@@ -75,14 +478,21 @@ impl Window {
         self.pending_increment = 0;
 
         if self.underflow < incr {
-            let incr = incr - self.underflow;
+            let mut incr = incr - self.underflow;
             debug_assert!(0 < incr);
+            let max = cmp::min(max, self.max_increment);
+            if max < incr {
+                self.pending_increment = incr - max;
+                incr = max;
+            }
             self.advertised += incr;
             self.underflow = 0;
+            self.notify_capacity();
             return Some(incr);
         }
 
-        debug_assert_eq!(self.advertised, 0);
+        // The window may have been shrunk after the increment was saved, while capacity
+        // was still advertised, so the increment only compensates for the underflow.
         self.underflow -= incr;
         None
     }
@@ -103,6 +513,38 @@ impl Window {
             panic!("illegal window underflow");
         }
         self.advertised -= decr;
+        self.claimed += decr;
+    }
+
+    /// Removes up to `sz` bytes of available capacity so that they may be pushed later,
+    /// returning the number of bytes reserved.
+    pub fn reserve(&mut self, sz: usize) -> usize {
+        let sz = cmp::min(sz, self.available());
+        self.advertised -= sz;
+        sz
+    }
+
+    /// Accounts for reserved capacity that has been pushed.
+    pub fn claim_reserved(&mut self, sz: usize) {
+        self.claimed += sz;
+    }
+
+    /// Returns unused reserved capacity to the window.
+    pub fn release_reserved(&mut self, sz: usize) {
+        if sz == 0 {
+            return;
+        }
+        self.advertised += sz;
+        self.notify_capacity();
+    }
+
+    /// Consumes capacity beyond what has been advertised.
+    ///
+    /// The overdraft is recorded as underflow, so it is repaid by the next increments
+    /// before they add capacity.
+    pub fn overdraw(&mut self, sz: usize) {
+        self.underflow += sz;
+        self.claimed += sz;
     }
 
     /// Eventually removes capacity from the window.
@@ -124,8 +566,10 @@ impl Window {
 mod test {
     use super::*;
     use futures::{Async, Poll, Stream};
+    use futures::executor::{self, Notify, NotifyHandle};
     use std::cell::RefCell;
     use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use test_futures::*;
 
     // TODO test that the task is notified on state change.
@@ -170,13 +614,219 @@ mod test {
         assert_eq!(win.borrow().advertised(), 4);
     }
 
+    #[test]
+    fn poll_fails_when_closed() {
+        let win = Rc::new(RefCell::new(Window::new(8)));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 8);
+        sassert_empty(&mut wstream);
+
+        win.borrow_mut().advertise_increment(4);
+        win.borrow_mut().close();
+        sassert_next(&mut wstream, 4);
+        sassert_err(&mut wstream, ());
+    }
+
+    #[test]
+    fn credit_now_applies_all_pending_increments() {
+        let mut win = Window::new(0);
+        win.set_max_increment(3);
+        win.advertise_increment(4);
+        win.advertise_increment(2);
+        assert_eq!(win.advertised(), 0);
+
+        assert_eq!(win.credit_now(), 6);
+        assert_eq!(win.advertised(), 6);
+        assert_eq!(win.snapshot().pending, 0);
+        assert_eq!(win.credit_now(), 0);
+    }
+
+    #[test]
+    fn shrink_after_pending_increment() {
+        let win = Rc::new(RefCell::new(Window::new(8)));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 8);
+
+        win.borrow_mut().claim_advertised(4);
+        win.borrow_mut().advertise_increment(3);
+        win.borrow_mut().shrink(5);
+        sassert_empty(&mut wstream);
+        assert_eq!(
+            win.borrow().snapshot(),
+            WindowSnapshot {
+                advertised: 4,
+                pending: 0,
+                underflow: 2,
+            }
+        );
+
+        win.borrow_mut().advertise_increment(3);
+        sassert_next(&mut wstream, 1);
+    }
+
+    #[test]
+    fn burst_settles_at_steady_state() {
+        let win = Rc::new(RefCell::new(Window::new_with_burst(4, 10)));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 10);
+
+        win.borrow_mut().claim_advertised(10);
+        win.borrow_mut().return_claimed(5);
+        sassert_empty(&mut wstream);
+        win.borrow_mut().return_claimed(5);
+        sassert_next(&mut wstream, 4);
+        assert_eq!(win.borrow().advertised(), 4);
+
+        // Once the burst has been spent, all returned capacity is advertised.
+        win.borrow_mut().claim_advertised(4);
+        win.borrow_mut().return_claimed(4);
+        sassert_next(&mut wstream, 4);
+        assert_eq!(win.borrow().advertised(), 4);
+    }
+
+    #[test]
+    fn snapshot_round_trips() {
+        let mut win = Window::new(8);
+        assert_eq!(
+            win.snapshot(),
+            WindowSnapshot {
+                advertised: 0,
+                pending: 8,
+                underflow: 0,
+            }
+        );
+
+        assert_eq!(win.apply_increment(usize::MAX), Some(8));
+        win.claim_advertised(6);
+        win.shrink(4);
+        win.advertise_increment(1);
+        let snap = win.snapshot();
+        assert_eq!(
+            snap,
+            WindowSnapshot {
+                advertised: 2,
+                pending: 0,
+                underflow: 3,
+            }
+        );
+
+        let restored = Rc::new(RefCell::new(Window::new(0)));
+        restored.borrow_mut().restore(snap);
+        assert_eq!(restored.borrow().snapshot(), snap);
+
+        // The restored window repays its underflow before advertising new capacity.
+        let mut rstream = WindowStream(restored.clone());
+        restored.borrow_mut().advertise_increment(3);
+        sassert_empty(&mut rstream);
+        restored.borrow_mut().advertise_increment(2);
+        sassert_next(&mut rstream, 2);
+        assert_eq!(restored.borrow().advertised(), 4);
+
+        let pending = WindowSnapshot {
+            advertised: 5,
+            pending: 7,
+            underflow: 0,
+        };
+        restored.borrow_mut().restore(pending);
+        assert_eq!(restored.borrow().snapshot(), pending);
+        sassert_next(&mut rstream, 7);
+        assert_eq!(restored.borrow().advertised(), 12);
+    }
+
+    #[test]
+    fn notify_threshold_is_bounded_by_window() {
+        assert_eq!(NotifyThreshold::Bytes(4).bytes(8), 4);
+        assert_eq!(NotifyThreshold::Bytes(16).bytes(8), 8);
+        assert_eq!(NotifyThreshold::Bytes(0).bytes(8), 1);
+        assert_eq!(NotifyThreshold::Ratio(0.25).bytes(10), 3);
+        assert_eq!(NotifyThreshold::Ratio(2.0).bytes(10), 10);
+
+        // Pending increments below the threshold are still applied when polled.
+        let win = Rc::new(RefCell::new(Window::with_threshold(8, 8, NotifyThreshold::Bytes(4))));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 8);
+        win.borrow_mut().claim_advertised(8);
+        win.borrow_mut().return_claimed(2);
+        sassert_next(&mut wstream, 2);
+    }
+
+    #[test]
+    fn shrunk_window_wakes_advertiser_below_threshold() {
+        let win = Rc::new(RefCell::new(Window::with_threshold(8, 8, NotifyThreshold::Bytes(8))));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 8);
+        win.borrow_mut().claim_advertised(8);
+        win.borrow_mut().shrink(4);
+
+        let count = Arc::new(Counter(AtomicUsize::new(0)));
+        let mut wstream = executor::spawn(wstream);
+        let notify = NotifyHandle::from(count.clone());
+        assert_eq!(wstream.poll_stream_notify(&notify, 0), Ok(Async::NotReady));
+
+        // Half of the returned capacity repays the shrink, so the threshold is never
+        // reached, but all of the remaining window is pending.
+        win.borrow_mut().return_claimed(8);
+        assert_eq!(win.borrow().snapshot().pending, 4);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(wstream.poll_stream_notify(&notify, 0), Ok(Async::Ready(Some(4))));
+    }
+
+    #[test]
+    fn waiting_sender_wakes_advertiser_below_threshold() {
+        let win = Rc::new(RefCell::new(Window::with_threshold(8, 8, NotifyThreshold::Bytes(8))));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 8);
+        win.borrow_mut().claim_advertised(8);
+
+        let count = Arc::new(Counter(AtomicUsize::new(0)));
+        let mut wstream = executor::spawn(wstream);
+        let notify = NotifyHandle::from(count.clone());
+        assert_eq!(wstream.poll_stream_notify(&notify, 0), Ok(Async::NotReady));
+
+        // A held chunk keeps the pending capacity below the threshold.
+        win.borrow_mut().return_claimed(6);
+        assert_eq!(count.0.load(Ordering::SeqCst), 0);
+
+        let mut capacity = executor::spawn(::futures::future::poll_fn(|| {
+            Ok::<_, ()>(win.borrow_mut().poll_advertised())
+        }));
+        assert_eq!(capacity.poll_future_notify(&notify_noop(), 0), Ok(Async::NotReady));
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(wstream.poll_stream_notify(&notify, 0), Ok(Async::Ready(Some(6))));
+    }
+
+    #[test]
+    fn sender_low_water_is_met_by_the_remaining_total() {
+        let win = Rc::new(RefCell::new(Window::new(8)));
+        let mut wstream = WindowStream(win.clone());
+        sassert_next(&mut wstream, 8);
+        win.borrow_mut().set_total_limit(6);
+        win.borrow_mut().set_sender_low_water(4);
+        win.borrow_mut().claim_advertised(4);
+        win.borrow_mut().count_total(4);
+
+        // Only 2 bytes may still be pushed, so the sender needn't wait for 4.
+        let mut capacity = executor::spawn(::futures::future::poll_fn(|| {
+            Ok::<_, ()>(win.borrow_mut().poll_advertised())
+        }));
+        assert_eq!(capacity.poll_future_notify(&notify_noop(), 0), Ok(Async::Ready(2)));
+    }
+
+    struct Counter(AtomicUsize);
+
+    impl Notify for Counter {
+        fn notify(&self, _id: usize) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
     struct WindowStream(Rc<RefCell<Window>>);
     impl Stream for WindowStream {
         type Item = usize;
         type Error = ();
         fn poll(&mut self) -> Poll<Option<usize>, ()> {
             let mut win = self.0.borrow_mut();
-            let sz = try_ready!(win.poll_increment());
+            let sz = try_ready!(win.poll_increment(usize::MAX));
             Ok(Async::Ready(Some(sz)))
         }
     }
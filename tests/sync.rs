@@ -1,13 +1,42 @@
 extern crate byte_channel;
 extern crate bytes;
 extern crate futures;
+#[cfg(feature = "futures03")]
+extern crate futures_core;
+extern crate iovec;
+#[cfg(feature = "metrics")]
+extern crate metrics;
+extern crate quickcheck;
 extern crate test_futures;
+#[cfg(feature = "tokio-io")]
+extern crate tokio_io;
 
 use bytes::*;
 use byte_channel::*;
-use futures::{Async, Poll, Stream, executor};
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream, executor};
+use futures::executor::{Notify, NotifyHandle};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use test_futures::*;
 
+/// Counts notifications.
+struct Counter(AtomicUsize);
+impl Counter {
+    fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+impl Notify for Counter {
+    fn notify(&self, _id: usize) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn notify_count() -> (Arc<Counter>, NotifyHandle) {
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    (counter.clone(), NotifyHandle::from(counter))
+}
+
 struct Reader(sync::ByteReceiver<()>, usize);
 impl Reader {
     fn resize(self, sz: usize) -> Reader {
@@ -28,6 +57,7 @@ impl Stream for Reader {
 }
 
 #[test]
+#[allow(clippy::unnecessary_cast)]
 fn consuming_chunks_frees_space() {
     let (mut wx, mut tx, rx) = sync::new::<()>(10);
     let mut rx = Reader(rx, 0);
@@ -132,3 +162,3627 @@ fn shrink_between_advertise_and_push() {
     assert_eq!(tx.available_window(), 0);
 
 }
+
+#[test]
+fn poll_chunk_until_deadline() {
+    use std::time::{Duration, Instant};
+
+    let (mut wx, mut tx, mut rx) = sync::new::<TimedOut>(10);
+    sassert_next(&mut wx, 10);
+
+    let past = Instant::now();
+    let future = Instant::now() + Duration::from_secs(60);
+    let mut task = executor::spawn(futures::future::lazy(|| -> Result<(), ()> {
+        match rx.poll_chunk_until(10, past) {
+            Err(TimedOut) => {}
+            res => panic!("expected timeout: {:?}", res),
+        }
+        match rx.poll_chunk_until(10, future) {
+            Ok(Async::NotReady) => {}
+            res => panic!("expected not ready: {:?}", res),
+        }
+
+        tx.push_bytes(Bytes::from("0123")).unwrap();
+        match rx.poll_chunk_until(10, past) {
+            Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+            res => panic!("expected chunk: {:?}", res),
+        }
+        Ok(())
+    }));
+    task.poll_future_notify(&notify_noop(), 0).unwrap();
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn checksums_agree() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    let mut rx = Reader(rx, 3);
+    sassert_next(&mut wx, 8);
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("4567")).unwrap();
+
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(chunk.remaining(), 3);
+    assert_ne!(rx.0.checksum(), tx.checksum());
+
+    let sent = tx.checksum();
+    drop(tx);
+    rx = rx.resize(10);
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(chunk.remaining(), 5);
+    sassert_done(&mut rx);
+    assert_eq!(rx.0.checksum(), sent);
+
+    // Cloned senders share the channel's checksum.
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    let mut tx2 = tx.clone();
+    tx2.push_bytes(Bytes::from("23")).unwrap();
+    tx.push_bytes(Bytes::from("45")).unwrap();
+    assert_eq!(tx.checksum(), tx2.checksum());
+
+    assert_eq!(rx.try_recv(8).unwrap().collect::<Vec<_>>(), b"012345");
+    assert_eq!(rx.checksum(), tx.checksum());
+}
+
+#[test]
+fn max_advertisement_splits_increments() {
+    let (wx, mut tx, rx) = sync::new::<()>(10);
+    let mut wx = wx.with_max_advertisement(4);
+    let mut rx = Reader(rx, 10);
+
+    sassert_next(&mut wx, 4);
+    sassert_next(&mut wx, 4);
+    sassert_next(&mut wx, 2);
+    sassert_empty(&mut wx);
+    assert_eq!(tx.available_window(), 10);
+
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    drop(chunk);
+    sassert_next(&mut wx, 4);
+    sassert_next(&mut wx, 4);
+    sassert_next(&mut wx, 2);
+    sassert_empty(&mut wx);
+    assert_eq!(tx.available_window(), 10);
+}
+
+#[test]
+fn split_at_offset_reads_head_then_body() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let (mut head, mut body) = rx.split_at_offset(4);
+    sassert_next(&mut wx, 10);
+
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+    let mut body_task = executor::spawn(futures::future::poll_fn(|| body.poll_chunk(10)));
+    match body_task.poll_future_notify(&notify_noop(), 0) {
+        Ok(Async::NotReady) => {}
+        res => panic!("body read before head: {:?}", res),
+    }
+
+    let mut head_task = executor::spawn(futures::future::poll_fn(|| head.poll_chunk(3)));
+    match head_task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"012"),
+        res => panic!("stream error: {:?}", res),
+    }
+    match head_task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"3"),
+        res => panic!("stream error: {:?}", res),
+    }
+    match head_task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("head not done: {:?}", res),
+    }
+    assert_eq!(tx.available_window(), 4);
+
+    tx.push_bytes(Bytes::from("6789")).unwrap();
+    drop(tx);
+    match body_task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => {
+            assert_eq!(c.remaining(), 6);
+            assert_eq!(c.bytes(), b"45");
+        }
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn split_head_outlives_dropped_body() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let (mut head, body) = rx.split_at_offset(4);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+
+    // The head still reads buffered data once the body has been dropped.
+    drop(body);
+    let mut head_task = executor::spawn(futures::future::poll_fn(|| head.poll_chunk(8)));
+    match head_task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"0123"),
+        res => panic!("expected head: {:?}", res),
+    }
+    tx.push_bytes(Bytes::from("6")).unwrap();
+
+    // The channel is torn down once both halves are gone.
+    drop(head_task);
+    drop(head);
+    assert_eq!(tx.push_bytes(Bytes::from("7")), Err(PushError::LostReceiver));
+}
+
+#[test]
+fn split_head_leaves_failure_for_body() {
+    let (mut wx, mut tx, rx) = sync::new::<&'static str>(10);
+    let (mut head, mut body) = rx.split_at_offset(4);
+    let drained = Arc::new(AtomicUsize::new(0));
+    let d = drained.clone();
+    body.on_drain(move || {
+        d.fetch_add(1, Ordering::SeqCst);
+    });
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.reset("boom");
+
+    // The head ends early without taking the error.
+    let mut head_task = executor::spawn(futures::future::poll_fn(|| head.poll_chunk(8)));
+    match head_task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected head to end: {:?}", res),
+    }
+    drop(head_task);
+    assert_eq!(head.remaining(), 4);
+
+    let mut body_task = executor::spawn(futures::future::poll_fn(|| body.poll_chunk(8)));
+    match body_task.poll_future_notify(&notify_panic(), 0) {
+        Err("boom") => {}
+        res => panic!("expected body error: {:?}", res),
+    }
+    assert_eq!(drained.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn poll_until_delimiter() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+
+    tx.push_bytes(Bytes::from("ab\ncd")).unwrap();
+    tx.push_bytes(Bytes::from("ef")).unwrap();
+    tx.push_bytes(Bytes::from("g\nhij")).unwrap();
+
+    let mut lines = executor::spawn(futures::stream::poll_fn(|| rx.poll_until(b'\n', 16)));
+
+    // Delimiter within a single segment.
+    match lines.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, Bytes::from("ab\n")),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut wx, 3);
+    assert_eq!(tx.available_window(), 7);
+
+    // Delimiter across segments.
+    match lines.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, Bytes::from("cdefg\n")),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut wx, 6);
+    assert_eq!(tx.available_window(), 13);
+
+    // No delimiter buffered yet.
+    match lines.poll_stream_notify(&notify_noop(), 0) {
+        Ok(Async::NotReady) => {}
+        res => panic!("expected not ready: {:?}", res),
+    }
+
+    // Delimiter absent at close.
+    drop(tx);
+    match lines.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, Bytes::from("hij")),
+        res => panic!("stream error: {:?}", res),
+    }
+    match lines.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+}
+
+#[test]
+fn poll_until_respects_max() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    tx.push_bytes(Bytes::from("abcdef\n")).unwrap();
+
+    let mut lines = executor::spawn(futures::stream::poll_fn(|| rx.poll_until(b'\n', 4)));
+    match lines.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, Bytes::from("abcd")),
+        res => panic!("stream error: {:?}", res),
+    }
+    match lines.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, Bytes::from("ef\n")),
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn with_front_consumes_front_segment() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+
+    {
+        let mut front = executor::spawn(futures::future::poll_fn(|| {
+            rx.with_front(|b| (b.len(), b.to_vec()))
+        }));
+        match front.poll_future_notify(&notify_noop(), 0) {
+            Ok(Async::NotReady) => {}
+            res => panic!("expected not ready: {:?}", res),
+        }
+    }
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("45")).unwrap();
+
+    // Consume part of the front segment.
+    let mut front = executor::spawn(futures::future::poll_fn(|| {
+        rx.with_front(|b| (1, b.to_vec()))
+    }));
+    match front.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, b"0123"),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut wx, 1);
+
+    // Consume the rest of the front segment.
+    let mut front = executor::spawn(futures::future::poll_fn(|| {
+        rx.with_front(|b| (b.len(), b.to_vec()))
+    }));
+    match front.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, b"123"),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut wx, 3);
+    match front.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(b))) => assert_eq!(b, b"45"),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut wx, 2);
+    assert_eq!(tx.available_window(), 8);
+
+    drop(tx);
+    match front.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+}
+
+#[test]
+fn with_front_calls_closure_unlocked() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("ping")).unwrap();
+
+    // The closure may push into the channel it reads from.
+    let mut front = executor::spawn(futures::future::poll_fn(|| {
+        rx.with_front(|b| {
+            assert_eq!(b, b"ping");
+            tx.push_bytes(Bytes::from("pong")).unwrap();
+            (b.len(), ())
+        })
+    }));
+    match front.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(()))) => {}
+        res => panic!("stream error: {:?}", res),
+    }
+    drop(front);
+    assert_eq!(rx.try_take_all(), Err(sync::TakeAllError::Open));
+    assert_eq!(tx.len(), 4);
+    sassert_next(&mut wx, 4);
+}
+
+#[test]
+fn reset_returns_unread_bytes_to_window() {
+    let (mut wx, mut tx, rx) = sync::new::<&'static str>(10);
+    sassert_next(&mut wx, 10);
+
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+    let mut rx = executor::spawn(futures::stream::poll_fn({
+        let mut rx = rx;
+        move || rx.poll_chunk(6)
+    }));
+    let chunk = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(chunk.remaining(), 6);
+    sassert_empty(&mut wx);
+
+    // The unread bytes are returned when the sender resets.
+    tx.reset("oops");
+    sassert_next(&mut wx, 4);
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Err(e) => assert_eq!(e, "oops"),
+        res => panic!("expected error: {:?}", res),
+    }
+
+    // The held chunk is returned when it's dropped.
+    drop(chunk);
+    sassert_next(&mut wx, 6);
+    sassert_empty(&mut wx);
+    assert_eq!(wx.snapshot().advertised, 10);
+
+    drop(rx);
+    sassert_done(&mut wx);
+}
+
+#[test]
+fn reset_after_buffered_with_held_chunk_restores_window() {
+    let (mut wx, mut tx, rx) = sync::new::<&'static str>(10);
+    sassert_next(&mut wx, 10);
+
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+    let mut rx = executor::spawn(futures::stream::poll_fn({
+        let mut rx = rx;
+        move || rx.poll_chunk(6)
+    }));
+    let held = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(held.remaining(), 6);
+
+    // The buffered bytes are delivered before the error rather than credited by the
+    // reset.
+    tx.reset_after_buffered("oops");
+    sassert_empty(&mut wx);
+    let rest = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(rest.bytes(), b"6789");
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Err(e) => assert_eq!(e, "oops"),
+        res => panic!("expected error: {:?}", res),
+    }
+
+    // Both chunks credit the window as they're dropped.
+    drop(rest);
+    sassert_next(&mut wx, 4);
+    drop(held);
+    sassert_next(&mut wx, 6);
+    sassert_empty(&mut wx);
+    assert_eq!(wx.snapshot().advertised, 10);
+
+    drop(rx);
+    sassert_done(&mut wx);
+}
+
+#[test]
+fn reset_with_held_chunk_credits_discarded_bytes() {
+    let (mut wx, mut tx, rx) = sync::new::<&'static str>(10);
+    sassert_next(&mut wx, 10);
+
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+    let mut rx = executor::spawn(futures::stream::poll_fn({
+        let mut rx = rx;
+        move || rx.poll_chunk(6)
+    }));
+    let held = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(held.remaining(), 6);
+
+    // The unread bytes are discarded and credited by the reset itself.
+    tx.reset("oops");
+    sassert_next(&mut wx, 4);
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Err(e) => assert_eq!(e, "oops"),
+        res => panic!("expected error: {:?}", res),
+    }
+
+    // The held chunk credits the rest once it's dropped after the error.
+    sassert_empty(&mut wx);
+    drop(held);
+    sassert_next(&mut wx, 6);
+    sassert_empty(&mut wx);
+    assert_eq!(wx.snapshot().advertised, 10);
+}
+
+#[test]
+fn reset_notifies_waiting_receiver() {
+    let (mut wx, tx, mut rx) = sync::new::<&'static str>(10);
+    sassert_next(&mut wx, 10);
+
+    let (count, notify) = notify_count();
+    let mut rx = executor::spawn(futures::stream::poll_fn(move || rx.poll_chunk(10)));
+    match rx.poll_stream_notify(&notify, 0) {
+        Ok(Async::NotReady) => {}
+        res => panic!("expected not ready: {:?}", res),
+    }
+    assert_eq!(count.get(), 0);
+
+    tx.reset("oops");
+    assert_eq!(count.get(), 1);
+    match rx.poll_stream_notify(&notify, 0) {
+        Err(e) => assert_eq!(e, "oops"),
+        res => panic!("expected error: {:?}", res),
+    }
+}
+
+#[test]
+fn push_errors_are_errors() {
+    use std::error::Error;
+
+    assert_eq!(LostReceiver.to_string(), "byte channel receiver was dropped");
+    assert_eq!(PushError::LostReceiver.to_string(), "byte channel receiver was dropped");
+    let overflow = PushError::Overflow { available: 4, needed: 5 };
+    assert_eq!(overflow.to_string(), "push of 5 bytes exceeds byte channel window of 4 bytes");
+
+    let boxed: Box<dyn Error> = Box::new(LostReceiver);
+    assert_eq!(boxed.to_string(), "byte channel receiver was dropped");
+    let boxed: Box<dyn Error> = Box::new(PushError::Closed);
+    assert_eq!(boxed.to_string(), "byte channel was closed by another sender");
+}
+
+#[test]
+fn total_limit_accepts_exact_pushes() {
+    let (mut wx, mut tx, mut rx) = sync::new_with_limit::<LimitExceeded>(8, 6);
+    let observer = rx.window_observer();
+    sassert_next(&mut wx, 8);
+    assert_eq!(tx.available_window(), 6);
+    assert_eq!(observer.available(), 6);
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(tx.available_window(), 2);
+    assert_eq!(observer.available(), 2);
+    tx.push_bytes(Bytes::from("45")).unwrap();
+    assert_eq!(tx.available_window(), 0);
+    assert_eq!(observer.available(), 0);
+    assert_eq!(tx.poll_capacity(), Err(PushError::LimitExceeded));
+    tx.close();
+
+    assert_eq!(rx.try_recv(8).unwrap().collect::<Vec<_>>(), b"012345");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Closed));
+}
+
+#[test]
+fn total_limit_fails_straddling_push() {
+    let (mut wx, mut tx, mut rx) = sync::new_with_limit::<LimitExceeded>(8, 6);
+    sassert_next(&mut wx, 8);
+    let mut tx2 = tx.clone();
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(tx.push_bytes(Bytes::from("4567")), Err(PushError::LimitExceeded));
+    assert_eq!(tx.len(), 4);
+    assert_eq!(tx2.push_bytes(Bytes::from("4")), Err(PushError::Closed));
+
+    // Partial pushes are capped rather than failing.
+    let (mut wx, mut tx3, _rx3) = sync::new_with_limit::<LimitExceeded>(8, 6);
+    sassert_next(&mut wx, 8);
+    let rest = tx3.push_bytes_partial(Bytes::from("01234567")).unwrap();
+    assert_eq!(rest, Bytes::from("67"));
+
+    // The bytes pushed before the limit are read before the stream fails.
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"0123");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Failed(LimitExceeded)));
+}
+
+#[test]
+fn builder_total_limit_rejects_push_without_failing() {
+    let (mut wx, mut tx, mut rx) = sync::Builder::new()
+        .window_size(8)
+        .total_limit(6)
+        .build::<()>();
+    sassert_next(&mut wx, 8);
+    assert_eq!(tx.available_window(), 6);
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(tx.push_bytes(Bytes::from("4567")), Err(PushError::LimitExceeded));
+    tx.push_bytes(Bytes::from("45")).unwrap();
+    assert_eq!(tx.available_window(), 0);
+    tx.close();
+
+    assert_eq!(rx.try_recv(8).unwrap().collect::<Vec<_>>(), b"012345");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Closed));
+}
+
+#[test]
+fn sink_fails_at_exhausted_total_limit() {
+    let (mut wx, tx, mut rx) = sync::new_with_limit::<LimitExceeded>(100, 4);
+    sassert_next(&mut wx, 100);
+    let mut tx = executor::spawn(tx);
+
+    match tx.start_send_notify(Bytes::from("01234567"), &notify_panic(), 0) {
+        Ok(AsyncSink::Ready) => {}
+        res => panic!("expected item to be accepted: {:?}", res),
+    }
+    assert_eq!(
+        tx.poll_flush_notify(&notify_panic(), 0),
+        Err(PushError::LimitExceeded)
+    );
+    assert_eq!(tx.get_mut().poll_capacity(), Err(PushError::Closed));
+
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"0123");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Failed(LimitExceeded)));
+}
+
+#[test]
+fn async_writer_fails_at_exhausted_total_limit() {
+    use std::io;
+
+    let (mut wx, tx, mut rx) = sync::new_with_limit::<LimitExceeded>(100, 4);
+    sassert_next(&mut wx, 100);
+    let mut writer = tx.into_async_write();
+
+    assert_eq!(write_in_task(&mut writer, b"0123").unwrap(), 4);
+    let err = write_in_task(&mut writer, b"4").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"0123");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Failed(LimitExceeded)));
+}
+
+#[test]
+fn try_push_bytes_rejects_overflow() {
+    let (mut wx, mut tx, _rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+
+    assert_eq!(
+        tx.try_push_bytes(Bytes::from("01234")),
+        Err(PushError::Overflow { available: 4, needed: 5 })
+    );
+    assert_eq!(tx.len(), 0);
+    assert_eq!(tx.available_window(), 4);
+
+    assert_eq!(tx.try_push_bytes(Bytes::from("0123")), Ok(4));
+    assert_eq!(tx.available_window(), 0);
+}
+
+#[test]
+#[should_panic(expected = "byte channel overflow")]
+fn push_bytes_panics_on_overflow() {
+    let (mut wx, mut tx, _rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+    let _ = tx.push_bytes(Bytes::from("01234"));
+}
+
+#[test]
+fn push_bytes_returns_buffered_len() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 3);
+    sassert_next(&mut wx, 10);
+
+    assert_eq!(tx.push_bytes(Bytes::from("0123")).unwrap(), 4);
+    assert_eq!(tx.push_bytes(Bytes::from("45")).unwrap(), 6);
+
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(chunk.remaining(), 3);
+    assert_eq!(tx.push_bytes(Bytes::from("6789")).unwrap(), 7);
+    assert_eq!(tx.len(), 7);
+}
+
+#[test]
+fn on_drain_fires_once_at_end_of_stream() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(10);
+    sassert_next(&mut wx, 10);
+
+    let drained = Arc::new(AtomicUsize::new(0));
+    rx.on_drain({
+        let drained = drained.clone();
+        move || {
+            drained.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    let mut rx = Reader(rx, 10);
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    drop(tx);
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(drained.load(Ordering::SeqCst), 0);
+
+    sassert_done(&mut rx);
+    assert_eq!(drained.load(Ordering::SeqCst), 1);
+    sassert_done(&mut rx);
+    assert_eq!(drained.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn on_drain_does_not_fire_on_error_or_drop() {
+    let drained = Arc::new(AtomicUsize::new(0));
+    let on_drain = || {
+        let drained = drained.clone();
+        move || {
+            drained.fetch_add(1, Ordering::SeqCst);
+        }
+    };
+
+    let (_wx, tx, mut rx) = sync::new::<()>(10);
+    rx.on_drain(on_drain());
+    let mut rx = Reader(rx, 10);
+    tx.reset(());
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Err(()) => {}
+        res => panic!("expected error: {:?}", res),
+    }
+    sassert_done(&mut rx);
+    assert_eq!(drained.load(Ordering::SeqCst), 0);
+
+    let (_wx, tx, mut rx) = sync::new::<()>(10);
+    rx.on_drain(on_drain());
+    drop(tx);
+    drop(rx);
+    assert_eq!(drained.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn grow_window_wakes_advertiser() {
+    let (wx, mut tx, rx) = sync::new::<()>(4);
+    let mut wx = executor::spawn(wx);
+    let (count, notify) = notify_count();
+    match wx.poll_stream_notify(&notify, 0) {
+        Ok(Async::Ready(Some(4))) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert!(wx.poll_stream_notify(&notify, 0).unwrap().is_not_ready());
+
+    // Growing the window mid-stream wakes the parked advertiser.
+    rx.grow_window(6);
+    assert_eq!(count.get(), 1);
+    assert_eq!(tx.available_window(), 0);
+    match wx.poll_stream_notify(&notify, 0) {
+        Ok(Async::Ready(Some(6))) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+    assert_eq!(tx.available_window(), 6);
+}
+
+#[test]
+fn notify_threshold_accumulates_small_increments() {
+    let (wx, mut tx, rx) = sync::Builder::new()
+        .window_size(8)
+        .notify_threshold(NotifyThreshold::Ratio(0.5))
+        .build::<()>();
+    let mut rx = Reader(rx, 8);
+    let mut wx = executor::spawn(wx);
+    let (count, notify) = notify_count();
+    match wx.poll_stream_notify(&notify, 0) {
+        Ok(Async::Ready(Some(8))) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+    tx.push_bytes(Bytes::from("01234567")).unwrap();
+    assert!(wx.poll_stream_notify(&notify, 0).unwrap().is_not_ready());
+
+    let mut chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res),
+    };
+
+    // Increments below half of the window accumulate without waking the advertiser.
+    chunk.advance(1);
+    chunk.advance(2);
+    assert_eq!(count.get(), 0);
+
+    // Crossing the threshold wakes it exactly once.
+    chunk.advance(1);
+    assert_eq!(count.get(), 1);
+    chunk.advance(2);
+    drop(chunk);
+    assert_eq!(count.get(), 1);
+
+    match wx.poll_stream_notify(&notify, 0) {
+        Ok(Async::Ready(Some(8))) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+}
+
+#[test]
+fn channel_stats_follow_window_arithmetic() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 4);
+    let expected = sync::ChannelStats {
+        advertised: 0,
+        pending: 10,
+        underflow: 0,
+        buffered: 0,
+        segments: Some(0),
+    };
+    assert_eq!(tx.channel_stats(), expected);
+    assert_eq!(wx.channel_stats(), sync::ChannelStats { segments: None, ..expected });
+
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("456")).unwrap();
+    let expected = sync::ChannelStats {
+        advertised: 3,
+        pending: 0,
+        underflow: 0,
+        buffered: 7,
+        segments: Some(2),
+    };
+    assert_eq!(tx.channel_stats(), expected);
+    assert_eq!(rx.0.channel_stats(), expected);
+
+    // Read bytes remain buffered until they are consumed.
+    let mut chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res),
+    };
+    assert_eq!(rx.0.channel_stats().buffered, 7);
+    assert_eq!(rx.0.channel_stats().segments, Some(1));
+    chunk.advance(4);
+    rx.shrink_window(2);
+    let expected = sync::ChannelStats {
+        advertised: 3,
+        pending: 4,
+        underflow: 2,
+        buffered: 3,
+        segments: Some(1),
+    };
+    assert_eq!(rx.0.channel_stats(), expected);
+    assert_eq!(wx.channel_stats(), sync::ChannelStats { segments: None, ..expected });
+}
+
+#[test]
+fn grow_window_interleaved_with_reads() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 4);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+
+    let c0 = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    rx.0.grow_window(5);
+    rx = rx.resize(3);
+    let c1 = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(tx.len(), 3);
+    assert_eq!(tx.available_window(), 0);
+
+    drop(c0);
+    rx.0.grow_window(1);
+    sassert_next(&mut wx, 10);
+    assert_eq!(tx.available_window(), 10);
+    assert_eq!(tx.len(), 3);
+
+    drop(c1);
+    sassert_next(&mut wx, 3);
+    sassert_empty(&mut wx);
+
+    // Everything buffered or held in chunks is accounted for, plus the growth.
+    assert_eq!(tx.available_window() + tx.len(), 10 + 6);
+}
+
+#[test]
+fn advertiser_grants_credit() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(0);
+    let mut rx = Reader(rx, 8);
+    sassert_empty(&mut wx);
+    assert_eq!(tx.available_window(), 0);
+
+    wx.advertise(6);
+    sassert_next(&mut wx, 6);
+    assert_eq!(tx.available_window(), 6);
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(chunk.bytes(), b"012345");
+    drop(chunk);
+    sassert_next(&mut wx, 6);
+    assert_eq!(tx.available_window(), 6);
+}
+
+#[test]
+fn sender_sinks_items_larger_than_window() {
+    let (mut wx, tx, rx) = sync::new::<()>(4);
+    let mut rx = Reader(rx, 8);
+    sassert_next(&mut wx, 4);
+
+    let items = futures::stream::iter_ok(vec![Bytes::from("012"), Bytes::from("3456789")]);
+    let (count, notify) = notify_count();
+    let mut send = executor::spawn(tx.send_all(items));
+    assert!(send.poll_future_notify(&notify, 0).unwrap().is_not_ready());
+
+    let read = |rx: &mut Reader| {
+        match executor::spawn(rx).poll_stream_notify(&notify_panic(), 0) {
+            Ok(Async::Ready(c)) => c.map(|c| c.collect::<Vec<u8>>()),
+            res => panic!("stream error: {:?}", res),
+        }
+    };
+
+    // The sender parks until the receiver returns capacity.
+    assert_eq!(read(&mut rx), Some(b"0123".to_vec()));
+    sassert_next(&mut wx, 4);
+    assert_eq!(count.get(), 1);
+    assert!(send.poll_future_notify(&notify, 0).unwrap().is_not_ready());
+
+    // Once the stream is exhausted, the sink is flushed and the channel closed.
+    assert_eq!(read(&mut rx), Some(b"4567".to_vec()));
+    sassert_next(&mut wx, 4);
+    assert_eq!(count.get(), 2);
+    assert!(send.poll_future_notify(&notify, 0).unwrap().is_ready());
+
+    assert_eq!(read(&mut rx), Some(b"89".to_vec()));
+    assert_eq!(read(&mut rx), None);
+}
+
+#[test]
+fn sender_sink_parks_on_zero_window() {
+    let (wx, tx, mut rx) = sync::new::<()>(4);
+    let mut wx = executor::spawn(wx);
+    let mut tx = executor::spawn(tx);
+    let (count, notify) = notify_count();
+
+    // Without any window, the item is handed back rather than held by the sender.
+    match tx.start_send_notify(Bytes::from("01"), &notify, 0) {
+        Ok(AsyncSink::NotReady(item)) => assert_eq!(item, Bytes::from("01")),
+        res => panic!("expected item to be handed back: {:?}", res),
+    }
+    assert_eq!(tx.get_ref().len(), 0);
+    assert_eq!(count.get(), 0);
+
+    assert_eq!(wx.poll_stream_notify(&notify_noop(), 0), Ok(Async::Ready(Some(4))));
+    assert_eq!(count.get(), 1);
+    match tx.start_send_notify(Bytes::from("01"), &notify, 0) {
+        Ok(AsyncSink::Ready) => {}
+        res => panic!("expected item to be accepted: {:?}", res),
+    }
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"01");
+}
+
+#[test]
+fn chunk_stream_ends_when_sender_dropped() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    sassert_next(&mut wx, 10);
+    let mut rx = executor::spawn(rx.into_chunks(4));
+    let mut read = || match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(c)) => c.map(|c| c.collect::<Vec<u8>>()),
+        res => panic!("stream error: {:?}", res),
+    };
+
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+    drop(tx);
+    assert_eq!(read(), Some(b"0123".to_vec()));
+    assert_eq!(read(), Some(b"4567".to_vec()));
+    assert_eq!(read(), Some(b"89".to_vec()));
+    assert_eq!(read(), None);
+}
+
+#[test]
+fn chunk_stream_resizes_chunks() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    sassert_next(&mut wx, 10);
+    let mut rx = rx.into_chunks(2);
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"01"),
+        res => panic!("stream error: {:?}", res),
+    }
+    rx.set_max_chunk(8);
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"23456789"),
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn into_stream_yields_segments() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("456")).unwrap();
+
+    let mut stream = rx.into_stream();
+    sassert_next(&mut stream, Bytes::from("0123"));
+    sassert_next(&mut wx, 4);
+    sassert_next(&mut stream, Bytes::from("456"));
+    sassert_next(&mut wx, 3);
+    sassert_empty(&mut stream);
+
+    tx.push_bytes(Bytes::from("789")).unwrap();
+    drop(tx);
+    sassert_next(&mut stream, Bytes::from("789"));
+    sassert_next(&mut wx, 3);
+    sassert_done(&mut stream);
+}
+
+#[test]
+fn chunk_holds_credit_until_consumed() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 0);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    let empty = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert!(!empty.holds_credit());
+
+    rx = rx.resize(4);
+    let mut chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert!(chunk.holds_credit());
+    chunk.advance(3);
+    assert!(chunk.holds_credit());
+    chunk.advance(1);
+    assert!(!chunk.holds_credit());
+}
+
+#[cfg(feature = "timer")]
+#[derive(Clone, Debug)]
+struct MockTimer(std::rc::Rc<std::cell::Cell<std::time::Instant>>);
+
+#[cfg(feature = "timer")]
+impl MockTimer {
+    fn new() -> MockTimer {
+        MockTimer(std::rc::Rc::new(std::cell::Cell::new(std::time::Instant::now())))
+    }
+
+    fn advance(&self, d: std::time::Duration) {
+        self.0.set(self.0.get() + d);
+    }
+}
+
+#[cfg(feature = "timer")]
+impl sync::Timer for MockTimer {
+    type Delay = futures::future::PollFn<Box<dyn FnMut() -> Poll<(), ()>>>;
+    fn now(&self) -> std::time::Instant {
+        self.0.get()
+    }
+    fn delay_until(&self, deadline: std::time::Instant) -> Self::Delay {
+        let clock = self.0.clone();
+        futures::future::poll_fn(Box::new(move || {
+            if deadline <= clock.get() {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "timer")]
+#[test]
+fn paced_advertiser_releases_credit_at_rate() {
+    use std::time::Duration;
+
+    let timer = MockTimer::new();
+    let (wx, tx, _rx) = sync::new::<()>(100);
+    let mut wx = wx.paced(10, timer.clone());
+
+    sassert_empty(&mut wx);
+    timer.advance(Duration::from_secs(1));
+    sassert_next(&mut wx, 10);
+    sassert_empty(&mut wx);
+
+    timer.advance(Duration::from_millis(500));
+    sassert_next(&mut wx, 5);
+    timer.advance(Duration::from_millis(50));
+    sassert_empty(&mut wx);
+    timer.advance(Duration::from_millis(50));
+    sassert_next(&mut wx, 1);
+
+    // No more than a second of credit accrues while idle.
+    timer.advance(Duration::from_secs(60));
+    sassert_next(&mut wx, 10);
+    sassert_empty(&mut wx);
+    assert_eq!(tx.available_window(), 26);
+}
+
+#[cfg(feature = "timer")]
+#[test]
+fn recv_timeout_fails_without_data() {
+    use std::time::Duration;
+
+    let timer = MockTimer::new();
+    let (mut wx, mut tx, mut rx) = sync::new::<TimedOut>(10);
+    sassert_next(&mut wx, 10);
+    let (count, notify) = notify_count();
+
+    {
+        let mut recv = executor::spawn(rx.recv_timeout(10, Duration::from_secs(1), &timer));
+        assert!(recv.poll_future_notify(&notify, 0).unwrap().is_not_ready());
+        timer.advance(Duration::from_secs(1));
+        match recv.poll_future_notify(&notify, 0) {
+            Err(TimedOut) => {}
+            res => panic!("expected timeout: {:?}", res),
+        }
+    }
+
+    // The timed-out read's task is not notified by a later push.
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(count.get(), 0);
+
+    let mut recv = executor::spawn(rx.recv_timeout(10, Duration::from_secs(1), &timer));
+    match recv.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("expected chunk: {:?}", res),
+    }
+}
+
+#[cfg(feature = "timer")]
+#[test]
+fn recv_timeout_resolves_when_data_arrives() {
+    use std::time::Duration;
+
+    let timer = MockTimer::new();
+    let (mut wx, mut tx, mut rx) = sync::new::<TimedOut>(10);
+    sassert_next(&mut wx, 10);
+    let (count, notify) = notify_count();
+
+    let mut recv = executor::spawn(rx.recv_timeout(10, Duration::from_secs(1), &timer));
+    assert!(recv.poll_future_notify(&notify, 0).unwrap().is_not_ready());
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(count.get(), 1);
+
+    // Data that arrived before the deadline is returned even if polled after it.
+    timer.advance(Duration::from_secs(2));
+    match recv.poll_future_notify(&notify, 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("expected chunk: {:?}", res),
+    }
+    drop(recv);
+
+    tx.close();
+    let mut recv = executor::spawn(rx.recv_timeout(10, Duration::from_secs(1), &timer));
+    match recv.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end of stream: {:?}", res),
+    }
+}
+
+#[test]
+fn chunk_alignment_delivers_whole_blocks() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(16);
+    rx.set_chunk_alignment(4);
+    let mut rx = Reader(rx, 16);
+    sassert_next(&mut wx, 16);
+
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    sassert_empty(&mut rx);
+
+    tx.push_bytes(Bytes::from("3456789")).unwrap();
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 8),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_empty(&mut rx);
+
+    rx = rx.resize(6);
+    tx.push_bytes(Bytes::from("abcde")).unwrap();
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_empty(&mut rx);
+
+    // The trailing partial block is only delivered on close.
+    drop(tx);
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 3),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_done(&mut rx);
+}
+
+#[test]
+fn try_take_all_when_closed() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(10);
+    sassert_next(&mut wx, 10);
+
+    match rx.try_take_all() {
+        Err(sync::TakeAllError::Open) => {}
+        res => panic!("expected open: {:?}", res),
+    }
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    match rx.try_take_all() {
+        Err(sync::TakeAllError::Open) => {}
+        res => panic!("expected open: {:?}", res),
+    }
+    assert_eq!(tx.len(), 4);
+
+    tx.push_bytes(Bytes::from("456")).unwrap();
+    drop(tx);
+    assert_eq!(rx.try_take_all().unwrap(), Bytes::from("0123456"));
+    sassert_next(&mut wx, 7);
+    assert_eq!(rx.try_take_all().unwrap(), Bytes::new());
+}
+
+#[test]
+fn push_after_close_by_clone() {
+    let (mut wx, tx0, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 10);
+    sassert_next(&mut wx, 10);
+
+    let mut tx1 = tx0.clone();
+    tx1.push_bytes(Bytes::from("0123")).unwrap();
+    tx0.close();
+    match tx1.push_bytes(Bytes::from("4567")) {
+        Err(PushError::Closed) => {}
+        res => panic!("expected closed: {:?}", res),
+    }
+    assert_eq!(tx1.available_window(), 6);
+
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_done(&mut rx);
+}
+
+#[test]
+fn push_after_close_ignored() {
+    let (mut wx, tx0, rx) = sync::Builder::new()
+        .window_size(10)
+        .on_push_after_close(sync::PushAfterClose::Ignore)
+        .build::<()>();
+    let mut rx = Reader(rx, 10);
+    sassert_next(&mut wx, 10);
+
+    let mut tx1 = tx0.clone();
+    tx1.push_bytes(Bytes::from("0123")).unwrap();
+    tx0.close();
+    assert_eq!(tx1.push_bytes(Bytes::from("4567")).unwrap(), 4);
+    assert_eq!(tx1.available_window(), 6);
+
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_done(&mut rx);
+    assert_eq!(tx1.push_bytes(Bytes::from("89")).unwrap(), 0);
+}
+
+#[test]
+fn channel_stays_open_until_all_senders_drop() {
+    let (mut wx, tx0, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 10);
+    sassert_next(&mut wx, 10);
+
+    let mut tx1 = tx0.clone();
+    drop(tx0);
+    sassert_empty(&mut rx);
+
+    tx1.push_bytes(Bytes::from("0123")).unwrap();
+    drop(tx1);
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.remaining(), 4),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_done(&mut rx);
+}
+
+#[test]
+fn receiver_cancel_fails_next_push() {
+    let (mut wx, mut tx, mut rx) = sync::new::<&'static str>(10);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    rx.cancel("downstream failed");
+    sassert_next(&mut wx, 4);
+    match tx.push_bytes(Bytes::from("4567")) {
+        Err(PushError::Cancelled) => {}
+        res => panic!("expected cancellation: {:?}", res),
+    }
+    assert_eq!(tx.take_cancellation(), Some("downstream failed"));
+    assert_eq!(tx.take_cancellation(), None);
+
+    let mut rx = executor::spawn(futures::stream::poll_fn(move || rx.poll_chunk(10)));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+    drop(rx);
+    match tx.push_bytes(Bytes::from("4567")) {
+        Err(PushError::Cancelled) => {}
+        res => panic!("expected cancellation: {:?}", res),
+    }
+}
+
+#[test]
+fn receiver_cancel_wakes_sender_awaiting_capacity() {
+    let (_wx, tx, mut rx) = sync::new::<&'static str>(0);
+    let mut tx = executor::spawn(tx);
+
+    let (count, notify) = notify_count();
+    match tx.poll_fn_notify(&notify, 0, |tx| tx.poll_capacity()) {
+        Ok(Async::NotReady) => {}
+        res => panic!("expected to wait for capacity: {:?}", res),
+    }
+
+    rx.cancel("downstream failed");
+    assert_eq!(count.get(), 1);
+    match tx.poll_fn_notify(&notify_panic(), 0, |tx| tx.poll_capacity()) {
+        Err(PushError::Cancelled) => {}
+        res => panic!("expected cancellation: {:?}", res),
+    }
+}
+
+#[test]
+fn receiver_reset_fails_sender_mid_stream() {
+    let (mut wx, tx, rx) = sync::new::<&'static str>(4);
+    sassert_next(&mut wx, 4);
+    let mut tx = executor::spawn(tx);
+    let mut rx = executor::spawn(rx);
+    tx.get_mut().push_bytes(Bytes::from("0123")).unwrap();
+
+    // The sender waits for capacity while the receiver holds a chunk and leaves the rest
+    // of the data buffered.
+    let (count, notify) = notify_count();
+    match tx.poll_fn_notify(&notify, 0, |tx| tx.poll_capacity()) {
+        Ok(Async::NotReady) => {}
+        res => panic!("expected to wait for capacity: {:?}", res),
+    }
+    let chunk = match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(2)) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    };
+
+    rx.into_inner().reset("protocol violation");
+    assert_eq!(count.get(), 1);
+    let mut tx = tx.into_inner();
+    match tx.push_bytes(Bytes::from("4")) {
+        Err(PushError::ReceiverFailed) => {}
+        res => panic!("expected receiver failure: {:?}", res),
+    }
+    assert_eq!(tx.take_cancellation(), Some("protocol violation"));
+    assert_eq!(tx.poll_capacity(), Err(PushError::ReceiverFailed));
+    assert_eq!(tx.poll_drained(), Err(PushError::ReceiverFailed));
+
+    // Unread bytes are returned immediately and the held chunk's on drop.
+    sassert_next(&mut wx, 2);
+    drop(chunk);
+    sassert_next(&mut wx, 2);
+    sassert_done(&mut wx);
+}
+
+#[test]
+fn drive_transfers_all_bytes_to_sink() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+
+    let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = {
+        let received = received.clone();
+        move |chunk: sync::Chunk| received.lock().unwrap().extend_from_slice(chunk.bytes())
+    };
+    let mut drive = executor::spawn(sync::drive(wx, rx, sink));
+
+    let mut input = Bytes::from("0123456789abcdef");
+    while !input.is_empty() {
+        let sz = std::cmp::min(tx.available_window(), input.len());
+        assert!(sz > 0, "window must be reopened by drive");
+        tx.push_bytes(input.split_to(sz)).unwrap();
+        assert_eq!(drive.poll_future_notify(&notify_noop(), 0), Ok(Async::NotReady));
+    }
+
+    drop(tx);
+    assert_eq!(drive.poll_future_notify(&notify_noop(), 0), Ok(Async::Ready(())));
+    assert_eq!(&received.lock().unwrap()[..], b"0123456789abcdef");
+}
+
+#[test]
+fn advertiser_ends_when_receiver_dropped() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    // The sender remains, but after the credit returned by the receiver, no further
+    // credit will be advertised.
+    drop(rx);
+    sassert_next(&mut wx, 4);
+    sassert_done(&mut wx);
+    assert!(tx.push_bytes(Bytes::from("4567")).is_err());
+}
+
+#[test]
+fn in_flight_counts_unconsumed_chunks() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("45")).unwrap();
+    assert_eq!(tx.in_flight(), 0);
+
+    let mut rx = executor::spawn(Reader(rx, 4));
+    let mut chunk = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(chunk))) => chunk,
+        res => panic!("expected chunk: {:?}", res),
+    };
+    assert_eq!(tx.len(), 2);
+    assert_eq!(tx.in_flight(), 4);
+
+    chunk.advance(1);
+    assert_eq!(tx.in_flight(), 3);
+    drop(chunk);
+    assert_eq!(tx.in_flight(), 0);
+    assert_eq!(tx.len(), 2);
+}
+
+#[test]
+fn from_reader_respects_window() {
+    use std::io::Cursor;
+
+    let data = (0..100u8).collect::<Vec<_>>();
+    let mut rx = sync::from_reader(Cursor::new(data.clone()), 16, 7);
+    let chunks = futures::stream::poll_fn(move || rx.poll_chunk(usize::MAX));
+
+    let mut received = Vec::new();
+    for chunk in chunks.wait() {
+        let chunk = chunk.unwrap();
+        assert!(chunk.len() <= 16);
+        received.extend_from_slice(&chunk.collect::<Vec<_>>());
+    }
+    assert_eq!(received, data);
+}
+
+#[test]
+fn from_reader_propagates_errors() {
+    use std::io::{self, Read};
+
+    struct Failing(usize);
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("boom"));
+            }
+            let sz = std::cmp::min(self.0, buf.len());
+            self.0 -= sz;
+            Ok(sz)
+        }
+    }
+
+    let mut rx = sync::from_reader(Failing(10), 4, 4);
+    let chunks = futures::stream::poll_fn(move || rx.poll_chunk(usize::MAX));
+
+    let mut received = 0;
+    for chunk in chunks.wait() {
+        match chunk {
+            Ok(chunk) => received += chunk.len(),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::Other);
+                assert!(received <= 10);
+                return;
+            }
+        }
+    }
+    panic!("expected read error");
+}
+
+#[test]
+fn sequential_polls_are_not_reentrant() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let (mut head, mut body) = rx.split_at_offset(2);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.close();
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| {
+        let head = head.poll_chunk(8);
+        let body = body.poll_chunk(8);
+        match (head, body) {
+            (Ok(Async::Ready(Some(h))), Ok(Async::Ready(Some(b)))) => Ok::<_, ()>(Async::Ready((h, b))),
+            (head, body) => panic!("unexpected reads: {:?} {:?}", head, body),
+        }
+    }));
+    match task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready((h, b))) => {
+            assert_eq!(h.bytes(), b"01");
+            assert_eq!(b.bytes(), b"23");
+        }
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "byte channel polled reentrantly")]
+fn reentrant_poll_panics() {
+    let (_wx, tx, rx) = sync::new::<()>(0);
+    let (mut head, mut body) = rx.split_at_offset(0);
+    drop(tx);
+    body.on_drain(move || {
+        let _ = head.poll_chunk(1);
+    });
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| body.poll_chunk(8)));
+    let _ = task.poll_future_notify(&notify_panic(), 0);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "byte channel polled reentrantly")]
+fn reentrant_poll_until_panics() {
+    let (_wx, tx, rx) = sync::new::<()>(0);
+    let (mut head, mut body) = rx.split_at_offset(0);
+    drop(tx);
+    body.on_drain(move || {
+        let _ = head.poll_chunk(1);
+    });
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| body.poll_until(b'\n', 8)));
+    let _ = task.poll_future_notify(&notify_panic(), 0);
+}
+
+#[test]
+fn push_bytes_partial_fills_window() {
+    let (mut wx, mut tx, _rx) = sync::new::<()>(6);
+    sassert_next(&mut wx, 6);
+
+    let rest = tx.push_bytes_partial(Bytes::from("0123")).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(tx.available_window(), 2);
+
+    let rest = tx.push_bytes_partial(Bytes::from("456789")).unwrap();
+    assert_eq!(rest, Bytes::from("6789"));
+    assert_eq!(tx.len(), 6);
+    assert_eq!(tx.available_window(), 0);
+
+    let rest = tx.push_bytes_partial(rest).unwrap();
+    assert_eq!(rest, Bytes::from("6789"));
+    assert_eq!(tx.len(), 6);
+}
+
+#[test]
+fn push_up_to_respects_smallest_cap() {
+    let (mut wx, mut tx, _rx) = sync::new::<()>(6);
+    sassert_next(&mut wx, 6);
+
+    // Capped by the caller.
+    let rest = tx.push_up_to(Bytes::from("0123456789"), 2).unwrap();
+    assert_eq!(rest, Bytes::from("23456789"));
+    assert_eq!(tx.len(), 2);
+
+    // Capped by the window.
+    let rest = tx.push_up_to(rest, 8).unwrap();
+    assert_eq!(rest, Bytes::from("6789"));
+    assert_eq!(tx.len(), 6);
+    assert_eq!(tx.available_window(), 0);
+
+    // Capped by the bytes themselves.
+    let (mut wx, mut tx, _rx) = sync::new::<()>(6);
+    sassert_next(&mut wx, 6);
+    let rest = tx.push_up_to(Bytes::from("012"), 4).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(tx.len(), 3);
+}
+
+#[test]
+fn reset_fails_stream_and_pushes() {
+    let (mut wx, mut tx, mut rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+    let mut tx2 = tx.clone();
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.reset("boom");
+
+    assert_eq!(tx2.push_bytes(Bytes::from("4567")), Err(PushError::Closed));
+    let mut rx = futures::stream::poll_fn(move || rx.poll_chunk(8));
+    sassert_err(&mut rx, "boom");
+}
+
+#[test]
+fn poll_chunk_with_window_reports_available() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    assert_eq!(tx.available_window(), 5);
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk_with_window(2)));
+    match task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some((chunk, available)))) => {
+            assert_eq!(chunk.bytes(), b"01");
+            assert_eq!(available, tx.available_window());
+        }
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn max_segments_per_chunk_bounds_reads() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(1000);
+    sassert_next(&mut wx, 1000);
+    for _ in 0..1000 {
+        tx.push_bytes(Bytes::from("x")).unwrap();
+    }
+    drop(tx);
+    rx.set_max_segments_per_chunk(64);
+
+    let mut rx = futures::stream::poll_fn(move || rx.poll_chunk(usize::MAX)).wait();
+    let mut total = 0;
+    for chunk in &mut rx {
+        let chunk = chunk.unwrap();
+        assert!(chunk.len() <= 64);
+        total += chunk.len();
+    }
+    assert_eq!(total, 1000);
+}
+
+#[test]
+#[cfg(feature = "debug-introspect")]
+fn buffered_segments_reports_layout() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    assert!(rx.buffered_segments().is_empty());
+
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    tx.push_bytes(Bytes::from("3")).unwrap();
+    tx.push_bytes(Bytes::from("45678")).unwrap();
+    assert_eq!(rx.buffered_segments(), vec![3, 1, 5]);
+
+    let mut rx = Reader(rx, 5);
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.len(), 5),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(rx.0.buffered_segments(), vec![4]);
+}
+
+#[test]
+fn reset_after_buffered_fails_after_data() {
+    let (mut wx, mut tx, mut rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+    let mut tx2 = tx.clone();
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    tx.push_bytes(Bytes::from("34")).unwrap();
+    tx.reset_after_buffered("boom");
+    assert_eq!(tx2.push_bytes(Bytes::from("5")), Err(PushError::Closed));
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(4)));
+    match task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.collect::<Vec<_>>(), b"0123"),
+        res => panic!("stream error: {:?}", res),
+    }
+    match task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"4"),
+        res => panic!("stream error: {:?}", res),
+    }
+    match task.poll_future_notify(&notify_panic(), 0) {
+        Err("boom") => {}
+        res => panic!("expected error: {:?}", res),
+    }
+}
+
+#[test]
+fn reset_after_buffered_notifies_waiting_receiver() {
+    let (mut wx, tx, mut rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+
+    let (counter, notify) = notify_count();
+    let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(4)));
+    assert!(task.poll_future_notify(&notify, 0).unwrap().is_not_ready());
+
+    tx.reset_after_buffered("boom");
+    assert_eq!(counter.get(), 1);
+    match task.poll_future_notify(&notify, 0) {
+        Err("boom") => {}
+        res => panic!("expected error: {:?}", res),
+    }
+}
+
+#[test]
+fn detect_stalls_on_zero_window() {
+    let (_wx, _tx, mut rx) = sync::new::<Stalled>(0);
+    rx.detect_stalls(3);
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(8)));
+    for _ in 0..2 {
+        assert!(task.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+    }
+    match task.poll_future_notify(&notify_noop(), 0) {
+        Err(Stalled) => {}
+        res => panic!("expected stall: {:?}", res),
+    }
+}
+
+#[test]
+fn detect_stalls_ignores_pending_window() {
+    let (_wx, _tx, mut rx) = sync::new::<Stalled>(8);
+    rx.detect_stalls(1);
+
+    // The advertiser hasn't been polled, but an increment is pending.
+    let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(8)));
+    for _ in 0..3 {
+        assert!(task.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+    }
+}
+
+#[test]
+fn segment_stream_credits_each_segment() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    tx.push_bytes(Bytes::from("34")).unwrap();
+    tx.push_bytes(Bytes::from("567")).unwrap();
+
+    let mut rx = Reader(rx, 8);
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    let mut segments: Box<dyn Stream<Item = Bytes, Error = Never>> =
+        Box::new(chunk.into_segment_stream());
+    sassert_next(&mut segments, Bytes::from("012"));
+    assert_eq!(tx.in_flight(), 5);
+    sassert_next(&mut segments, Bytes::from("34"));
+    assert_eq!(tx.in_flight(), 3);
+    sassert_next(&mut segments, Bytes::from("567"));
+    assert_eq!(tx.in_flight(), 0);
+    sassert_done(&mut segments);
+    sassert_next(&mut wx, 8);
+}
+
+#[test]
+fn segment_stream_dropped_early_credits_remainder() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    tx.push_bytes(Bytes::from("34")).unwrap();
+
+    let mut rx = Reader(rx, 8);
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    let mut segments = chunk.into_segment_stream();
+    sassert_next(&mut segments, Bytes::from("012"));
+    sassert_next(&mut wx, 3);
+
+    drop(segments);
+    assert_eq!(tx.in_flight(), 0);
+    sassert_next(&mut wx, 2);
+}
+
+#[test]
+#[cfg(feature = "metrics")]
+fn metrics_record_sender_and_receiver_park_time() {
+    use metrics::{Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+                  SharedString, Unit};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Observations(Mutex<Vec<f64>>);
+    impl HistogramFn for Observations {
+        fn record(&self, value: f64) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    #[derive(Default)]
+    struct MockRecorder(Mutex<Vec<(String, Arc<Observations>)>>);
+    impl Recorder for MockRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, _: &Key, _: &Metadata) -> Counter {
+            Counter::noop()
+        }
+        fn register_gauge(&self, _: &Key, _: &Metadata) -> Gauge {
+            Gauge::noop()
+        }
+        fn register_histogram(&self, key: &Key, _: &Metadata) -> Histogram {
+            let obs = Arc::new(Observations::default());
+            let label = key.labels().map(|l| l.value().to_owned()).collect::<Vec<_>>();
+            let name = format!("{}{:?}", key.name(), label);
+            self.0.lock().unwrap().push((name, obs.clone()));
+            Histogram::from_arc(obs)
+        }
+    }
+
+    let recorder = MockRecorder::default();
+    metrics::with_local_recorder(&recorder, || {
+        let (mut wx, mut tx, mut rx) = sync::Builder::new()
+            .window_size(4)
+            .metrics_label("test")
+            .build::<()>();
+        sassert_next(&mut wx, 4);
+
+        let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(4)));
+        assert!(task.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+        assert!(task.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+        tx.push_bytes(Bytes::from("0123")).unwrap();
+        let chunk = match task.poll_future_notify(&notify_noop(), 0) {
+            Ok(Async::Ready(Some(c))) => c,
+            res => panic!("stream error: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+        };
+        assert_eq!(chunk.len(), 4);
+
+        let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+        assert!(capacity.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+        assert!(capacity.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+        drop(chunk);
+        sassert_next(&mut wx, 4);
+        assert_eq!(capacity.poll_future_notify(&notify_noop(), 0), Ok(Async::Ready(4)));
+    });
+
+    let histograms = recorder.0.lock().unwrap();
+    let observations = |histogram: &str| {
+        histograms
+            .iter()
+            .filter(|&(name, _)| name == &format!("{}[\"test\"]", histogram))
+            .map(|(_, obs)| obs.0.lock().unwrap().len())
+            .sum::<usize>()
+    };
+    assert_eq!(observations("byte_channel_receiver_parked_seconds"), 1);
+    assert_eq!(observations("byte_channel_sender_parked_seconds"), 1);
+}
+
+#[test]
+fn coalesce_reads_yields_one_segment() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = Reader(rx, 8);
+
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    tx.push_bytes(Bytes::from("34")).unwrap();
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => {
+            assert_eq!(c.len(), 5);
+            assert_eq!(c.bytes(), b"012");
+        }
+        res => panic!("stream error: {:?}", res),
+    }
+
+    sassert_next(&mut wx, 5);
+    rx.0.set_coalesce_reads(true);
+    tx.push_bytes(Bytes::from("567")).unwrap();
+    tx.push_bytes(Bytes::from("89")).unwrap();
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => {
+            assert_eq!(c.len(), 5);
+            assert_eq!(c.bytes(), b"56789");
+        }
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn bounded_sender_waits_for_capacity() {
+    let (mut tx, rx) = sync::bounded::<()>(4);
+    let (counter, notify) = notify_count();
+
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::Ready(4)));
+    drop(capacity);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+    drop(capacity);
+
+    let mut rx = Reader(rx, 3);
+    let chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(counter.get(), 0);
+    drop(chunk);
+    assert_eq!(counter.get(), 1);
+
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::Ready(3)));
+    drop(capacity);
+    tx.push_bytes(Bytes::from("456")).unwrap();
+    tx.close();
+
+    let mut rx = executor::spawn(rx.resize(8));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.collect::<Vec<_>>(), b"3456"),
+        res => panic!("stream error: {:?}", res),
+    }
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+}
+
+#[test]
+fn bounded_sender_observes_lost_receiver() {
+    let (mut tx, rx) = sync::bounded::<()>(4);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    let (counter, notify) = notify_count();
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+    drop(rx);
+    assert_eq!(counter.get(), 1);
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Err(PushError::LostReceiver));
+}
+
+#[test]
+fn sender_reads_preferred_push_size() {
+    let (_wx, tx, mut rx) = sync::new::<()>(16);
+    assert_eq!(tx.preferred_push_size(), None);
+
+    rx.set_chunk_alignment(4);
+    assert_eq!(tx.preferred_push_size(), Some(4));
+
+    rx.set_chunk_alignment(1);
+    assert_eq!(tx.preferred_push_size(), None);
+}
+
+#[test]
+fn randomized_operations_keep_window_consistent() {
+    // A small xorshift PRNG, so that failures are reproducible from the seed.
+    struct Rng(u64);
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    for seed in 1..200 {
+        let mut rng = Rng(seed);
+        let initial = 1 + rng.below(32);
+        let (mut wx, mut tx, mut rx) = sync::new::<()>(initial);
+        let mut capacity = initial as i64;
+        let mut chunks = Vec::new();
+
+        for _ in 0..100 {
+            match rng.below(7) {
+                0 => {
+                    let sz = std::cmp::min(tx.available_window(), 1 + rng.below(8));
+                    tx.push_bytes(Bytes::from(vec![0; sz])).unwrap();
+                }
+                1 => {
+                    let max = 1 + rng.below(8);
+                    let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(max)));
+                    if let Ok(Async::Ready(Some(c))) = read.poll_future_notify(&notify_noop(), 0) {
+                        chunks.push(c);
+                    }
+                }
+                2 if !chunks.is_empty() => {
+                    let i = rng.below(chunks.len());
+                    let sz = rng.below(chunks[i].len() + 1);
+                    chunks[i].advance(sz);
+                }
+                3 if !chunks.is_empty() => {
+                    let i = rng.below(chunks.len());
+                    chunks.swap_remove(i);
+                }
+                4 => {
+                    let sz = rng.below(4);
+                    rx.shrink_window(sz);
+                    capacity -= sz as i64;
+                }
+                5 => {
+                    let sz = rng.below(4);
+                    rx.grow_window(sz);
+                    capacity += sz as i64;
+                }
+                _ => {
+                    let mut incr = executor::spawn(&mut wx);
+                    let _ = incr.poll_stream_notify(&notify_noop(), 0);
+                }
+            }
+        }
+
+        drop(tx);
+        loop {
+            let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(usize::MAX)));
+            match read.poll_future_notify(&notify_noop(), 0) {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(Async::Ready(None)) => break,
+                res => panic!("seed {}: unexpected read: {:?}", seed, res),
+            }
+        }
+        drop(chunks);
+        drop(rx);
+
+        let snap = wx.snapshot();
+        let net = (snap.advertised + snap.pending) as i64 - snap.underflow as i64;
+        assert_eq!(net, capacity, "seed {}: {:?}", seed, snap);
+    }
+}
+
+#[test]
+fn poll_chunk_no_park_does_not_save_task() {
+    let (mut wx, mut tx, mut rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+
+    let mut task = executor::spawn(futures::future::poll_fn(|| {
+        Ok::<_, ()>(Async::Ready(rx.poll_chunk_no_park(4)))
+    }));
+    match task.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(sync::PollNoPark::Pending)) => {}
+        res => panic!("expected pending: {:?}", res),
+    }
+    drop(task);
+
+    // notify_panic would fire if the receiver had been parked.
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+    match rx.poll_chunk_no_park(4) {
+        sync::PollNoPark::Data(c) => assert_eq!(c.bytes(), b"0123"),
+        res => panic!("expected data: {:?}", res),
+    }
+
+    tx.reset("boom");
+    match rx.poll_chunk_no_park(4) {
+        sync::PollNoPark::Failed("boom") => {}
+        res => panic!("expected failure: {:?}", res),
+    }
+}
+
+#[test]
+fn poll_chunk_does_not_park_when_disabled() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+
+    let mut rx = executor::spawn(rx);
+    rx.get_mut().set_park_on_empty(false);
+    assert!(rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(4)).unwrap().is_not_ready());
+    let polled = rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk_with_window(4));
+    assert!(polled.unwrap().is_not_ready());
+
+    // notify_panic would fire if the receiver had been parked.
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+    match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(4)) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"0123"),
+        res => panic!("expected data: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+}
+
+#[test]
+fn chunk_len_tracks_remaining() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    let mut rx = executor::spawn(rx);
+    let mut poll = |sz| match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(sz)) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    };
+
+    let empty = poll(0);
+    assert!(empty.is_empty());
+    assert_eq!(empty.len(), 0);
+    assert_eq!(empty.remaining(), 0);
+
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("234")).unwrap();
+    let mut chunk = poll(8);
+    for sz in (0..5).rev() {
+        chunk.advance(1);
+        assert_eq!(chunk.len(), sz);
+        assert_eq!(chunk.remaining(), sz);
+        assert_eq!(chunk.is_empty(), sz == 0);
+    }
+}
+
+#[test]
+fn coalesced_pushes_reduce_segments() {
+    const PUSHES: usize = 1000;
+
+    let push_bytes = |builder: sync::Builder| {
+        let (mut wx, mut tx, mut rx) = builder.window_size(PUSHES).build::<()>();
+        sassert_next(&mut wx, PUSHES);
+        for i in 0..PUSHES {
+            tx.push_bytes(Bytes::from(vec![i as u8])).unwrap();
+            assert_eq!(tx.available_window(), PUSHES - i - 1);
+        }
+        let segments = tx.channel_stats().segments;
+        let chunk = rx.try_recv(PUSHES).unwrap();
+        assert_eq!(chunk.len(), PUSHES);
+        let bytes = chunk.collect::<Vec<_>>();
+        assert!(bytes.iter().enumerate().all(|(i, b)| *b == i as u8));
+        sassert_next(&mut wx, PUSHES);
+        segments
+    };
+
+    assert_eq!(push_bytes(sync::Builder::new()), Some(PUSHES));
+    assert_eq!(push_bytes(sync::Builder::new().coalesce_pushes(64)), Some(16));
+}
+
+#[test]
+fn dropping_empty_chunk_does_not_wake_advertiser() {
+    let (wx, mut tx, rx) = sync::new::<()>(4);
+    let mut wx = executor::spawn(wx);
+    let (count, notify) = notify_count();
+    match wx.poll_stream_notify(&notify, 0) {
+        Ok(Async::Ready(Some(4))) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert!(wx.poll_stream_notify(&notify, 0).unwrap().is_not_ready());
+
+    let mut rx = executor::spawn(rx);
+    let empty = match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(0)) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    };
+    assert!(empty.is_empty());
+    assert!(!empty.holds_credit());
+    drop(empty);
+    assert_eq!(count.get(), 0);
+    assert!(wx.poll_stream_notify(&notify, 0).unwrap().is_not_ready());
+    assert_eq!(tx.available_window(), 0);
+}
+
+#[test]
+fn chunks_count_their_segments() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    let mut rx = executor::spawn(rx);
+    let mut poll = |sz| match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(sz)) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    };
+
+    assert_eq!(poll(0).segment_count(), 0);
+
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("234")).unwrap();
+    tx.push_bytes(Bytes::from("5678")).unwrap();
+    let mut chunk = poll(8);
+    assert_eq!(chunk.segment_count(), 3);
+    chunk.advance(1);
+    assert_eq!(chunk.segment_count(), 3);
+    chunk.advance(1);
+    assert_eq!(chunk.segment_count(), 2);
+    chunk.advance(5);
+    assert_eq!(chunk.segment_count(), 1);
+    chunk.advance(1);
+    assert_eq!(chunk.segment_count(), 0);
+
+    let mut chunk = poll(8);
+    assert_eq!(chunk.segment_count(), 1);
+    chunk.advance(1);
+    assert_eq!(chunk.segment_count(), 0);
+}
+
+#[test]
+fn chunks_expose_segments_for_vectored_writes() {
+    use iovec::IoVec;
+
+    let (mut wx, mut tx, rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    let mut rx = executor::spawn(rx);
+    let mut poll = |sz| match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(sz)) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    };
+
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("234")).unwrap();
+    tx.push_bytes(Bytes::from("5678")).unwrap();
+    let mut chunk = poll(8);
+
+    let unset: &IoVec = b"-"[..].into();
+    let mut dst = [unset; 4];
+    assert_eq!(chunk.bytes_vec(&mut dst), 3);
+    let segments: Vec<&[u8]> = dst[..3].iter().map(|v| &v[..]).collect();
+    assert_eq!(segments, vec![&b"01"[..], b"234", b"567"]);
+    assert_eq!(&dst[3][..], b"-");
+
+    // A short destination is filled with the leading segments.
+    chunk.advance(1);
+    let mut dst = [unset; 2];
+    assert_eq!(chunk.bytes_vec(&mut dst), 2);
+    assert_eq!(dst.iter().map(|v| v[..].len()).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(chunk.bytes_vec(&mut []), 0);
+
+    chunk.advance(7);
+    let mut dst = [unset; 2];
+    assert_eq!(chunk.bytes_vec(&mut dst), 0);
+
+    let chunk = poll(8);
+    let mut dst = [unset; 2];
+    assert_eq!(chunk.bytes_vec(&mut dst), 1);
+    assert_eq!(&dst[0][..], b"8");
+}
+
+#[test]
+fn poll_all_drains_every_buffered_byte() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    let mut rx = executor::spawn(rx);
+    rx.get_mut().set_max_segments_per_chunk(1);
+
+    let (count, notify) = notify_count();
+    match rx.poll_fn_notify(&notify, 0, |rx| rx.poll_all()) {
+        Ok(Async::NotReady) => {}
+        res => panic!("expected not ready: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    assert_eq!(count.get(), 1);
+    tx.push_bytes(Bytes::from("234")).unwrap();
+    tx.push_bytes(Bytes::from("56789")).unwrap();
+
+    let mut chunk = match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_all()) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    };
+    assert_eq!(chunk.len(), 10);
+    assert_eq!(chunk.segment_count(), 3);
+    assert_eq!(rx.get_ref().channel_stats().segments, Some(0));
+
+    // Capacity is returned as the chunk is consumed.
+    sassert_empty(&mut wx);
+    chunk.advance(4);
+    sassert_next(&mut wx, 4);
+    drop(chunk);
+    sassert_next(&mut wx, 6);
+
+    tx.close();
+    match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_all()) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+}
+
+#[test]
+fn poll_chunk_exact_waits_for_enough_bytes() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    let mut rx = executor::spawn(rx);
+    let (count, notify) = notify_count();
+
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    assert!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk_exact(4)).unwrap().is_not_ready());
+
+    tx.push_bytes(Bytes::from("234")).unwrap();
+    assert_eq!(count.get(), 1);
+    match rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk_exact(4)) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.collect::<Vec<_>>(), b"0123"),
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+
+    // Once the sender closes, a short chunk is returned.
+    assert!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk_exact(4)).unwrap().is_not_ready());
+    tx.close();
+    assert_eq!(count.get(), 2);
+    match rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk_exact(4)) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"4"),
+        res => panic!("expected chunk: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+    match rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk_exact(4)) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+}
+
+#[test]
+fn try_recv_outside_task() {
+    let (mut wx, mut tx, mut rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+    assert_eq!(rx.try_recv(4).err(), Some(sync::TryRecvError::Empty));
+
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+    assert_eq!(rx.try_recv(4).unwrap().bytes(), b"0123");
+    tx.close();
+    assert_eq!(rx.try_recv(4).unwrap().bytes(), b"45");
+    assert_eq!(rx.try_recv(4).err(), Some(sync::TryRecvError::Closed));
+
+    let (mut wx, tx, mut rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+    tx.reset("boom");
+    assert_eq!(rx.try_recv(4).err(), Some(sync::TryRecvError::Failed("boom")));
+}
+
+#[test]
+fn chunks_peek_without_consuming() {
+    use std::borrow::Cow;
+
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("2345")).unwrap();
+
+    let mut chunk = match rx.try_recv(8) {
+        Ok(c) => c,
+        res => panic!("expected chunk: {:?}", res.map(|c| c.len())),
+    };
+    match chunk.peek(2) {
+        Cow::Borrowed(b) => assert_eq!(b, b"01"),
+        p => panic!("expected borrowed bytes: {:?}", p),
+    }
+    match chunk.peek(4) {
+        Cow::Owned(b) => assert_eq!(b, b"0123"),
+        p => panic!("expected copied bytes: {:?}", p),
+    }
+    assert_eq!(&*chunk.peek(10), b"012345");
+    assert_eq!(chunk.len(), 6);
+    sassert_empty(&mut wx);
+
+    chunk.advance(3);
+    assert_eq!(&*chunk.peek(2), b"34");
+    assert_eq!(&*chunk.peek(0), b"");
+}
+
+#[test]
+fn split_chunks_return_their_own_capacity() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(16);
+    sassert_next(&mut wx, 16);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("2345")).unwrap();
+    tx.push_bytes(Bytes::from("6789")).unwrap();
+    assert_eq!(tx.available_window(), 6);
+
+    let mut chunk = rx.try_recv(16).unwrap();
+    let mut header = chunk.split_to(3);
+    assert_eq!(header.len(), 3);
+    assert_eq!(header.segment_count(), 2);
+    assert_eq!(chunk.len(), 7);
+    assert_eq!(chunk.peek(7).into_owned(), b"3456789");
+    sassert_empty(&mut wx);
+
+    // Splitting a single segment.
+    let frame = header.split_to(1);
+    assert_eq!(frame.bytes(), b"0");
+    assert_eq!(header.collect::<Vec<_>>(), b"12");
+    sassert_next(&mut wx, 2);
+    drop(frame);
+    sassert_next(&mut wx, 1);
+
+    // Splitting the entire remainder.
+    let rest = chunk.split_to(7);
+    assert!(chunk.is_empty());
+    drop(chunk);
+    sassert_empty(&mut wx);
+    drop(rest);
+    sassert_next(&mut wx, 7);
+    assert_eq!(tx.available_window(), 16);
+}
+
+#[test]
+fn chunk_to_bytes_returns_capacity() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("234")).unwrap();
+
+    let mut chunk = rx.try_recv(8).unwrap();
+    chunk.advance(1);
+    sassert_next(&mut wx, 1);
+    assert_eq!(chunk.to_bytes(), Bytes::from("1234"));
+    sassert_next(&mut wx, 4);
+
+    tx.push_bytes(Bytes::from("567")).unwrap();
+    assert_eq!(rx.try_recv(8).unwrap().to_bytes(), Bytes::from("567"));
+    sassert_next(&mut wx, 3);
+    assert_eq!(tx.available_window(), 8);
+}
+
+#[test]
+#[should_panic(expected = "split exceeds chunk size")]
+fn split_to_panics_past_end() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    let mut chunk = rx.try_recv(4).unwrap();
+    chunk.split_to(3);
+}
+
+#[test]
+fn poll_chunk_no_park_reports_close() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.close();
+
+    match rx.poll_chunk_no_park(4) {
+        sync::PollNoPark::Data(c) => assert_eq!(c.bytes(), b"01"),
+        res => panic!("expected data: {:?}", res),
+    }
+    match rx.poll_chunk_no_park(4) {
+        sync::PollNoPark::Closed => {}
+        res => panic!("expected close: {:?}", res),
+    }
+}
+
+#[test]
+fn stats_reflect_transfer() {
+    let (_wx, tx, rx) = sync::new::<()>(8);
+    assert_eq!(tx.stats(), None);
+    assert_eq!(rx.stats(), None);
+
+    let (mut wx, mut tx, rx) = sync::Builder::new()
+        .window_size(8)
+        .collect_stats(true)
+        .build::<()>();
+    sassert_next(&mut wx, 8);
+    let mut rx = Reader(rx, 4);
+
+    match executor::spawn(&mut rx).poll_stream_notify(&notify_noop(), 0) {
+        Ok(Async::NotReady) => {}
+        res => panic!("expected not ready: {:?}", res),
+    }
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    tx.push_bytes(Bytes::from("34")).unwrap();
+    let mut chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_noop(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    chunk.advance(1);
+
+    let stats = sync::ByteChannelStats {
+        bytes_pushed: 5,
+        bytes_consumed: 1,
+        blocked: 1,
+        high_water: 5,
+        outstanding_chunks: 1,
+    };
+    assert_eq!(tx.stats(), Some(stats));
+    assert_eq!(rx.0.stats(), Some(stats));
+
+    drop(chunk);
+    let stats = tx.stats().unwrap();
+    assert_eq!(stats.bytes_consumed, 4);
+    assert_eq!(stats.outstanding_chunks, 0);
+}
+
+#[test]
+fn lifo_reads_most_recent_first() {
+    let (mut wx, mut tx, rx) = sync::Builder::new().window_size(8).lifo(true).build::<()>();
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("ab")).unwrap();
+    tx.push_bytes(Bytes::from("cd")).unwrap();
+    tx.push_bytes(Bytes::from("ef")).unwrap();
+    tx.close();
+
+    let mut rx = executor::spawn(Reader(rx, 2));
+    for expected in &[b"ef", b"cd", b"ab"] {
+        match rx.poll_stream_notify(&notify_panic(), 0) {
+            Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), &expected[..]),
+            res => panic!("stream error: {:?}", res),
+        }
+    }
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+}
+
+#[test]
+fn lifo_limits_segments_from_the_back() {
+    let (mut wx, mut tx, rx) = sync::Builder::new().window_size(8).lifo(true).build::<()>();
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("abcde")).unwrap();
+    tx.push_bytes(Bytes::from("f")).unwrap();
+    tx.push_bytes(Bytes::from("g")).unwrap();
+    tx.close();
+
+    let mut rx = executor::spawn(rx);
+    rx.get_mut().set_max_segments_per_chunk(2);
+    let mut rx = executor::spawn(futures::stream::poll_fn(move || rx.get_mut().poll_chunk(8)));
+    for expected in &[&b"gf"[..], &b"abcde"[..]] {
+        match rx.poll_stream_notify(&notify_panic(), 0) {
+            Ok(Async::Ready(Some(c))) => assert_eq!(c.to_bytes(), Bytes::from(*expected)),
+            res => panic!("stream error: {:?}", res),
+        }
+    }
+}
+
+#[test]
+fn reserved_window_is_pushed_or_released() {
+    let (mut wx, mut tx, _rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+
+    assert_eq!(tx.try_reserve_window(3), 3);
+    assert_eq!(tx.available_window(), 5);
+    assert_eq!(tx.push_reserved(Bytes::from("012")), Ok(3));
+    assert_eq!(tx.available_window(), 5);
+    assert_eq!(tx.in_flight(), 0);
+
+    assert_eq!(tx.try_reserve_window(10), 5);
+    assert_eq!(tx.available_window(), 0);
+    assert_eq!(tx.push_reserved(Bytes::from("34")), Ok(5));
+    tx.release_reserved();
+    assert_eq!(tx.available_window(), 3);
+    assert_eq!(tx.len(), 5);
+}
+
+#[test]
+fn reservation_is_bounded_by_total_limit() {
+    let (mut wx, mut tx, mut rx) = sync::new_with_limit::<LimitExceeded>(8, 6);
+    sassert_next(&mut wx, 8);
+
+    assert_eq!(tx.try_reserve_window(8), 6);
+    assert_eq!(tx.push_reserved(Bytes::from("012345")), Ok(6));
+    tx.close();
+
+    assert_eq!(rx.try_recv(8).unwrap().collect::<Vec<_>>(), b"012345");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Closed));
+}
+
+#[test]
+fn read_staging_serves_small_reads_from_one_chunk() {
+    let (mut wx, mut tx, mut rx) = sync::new::<&'static str>(64);
+    sassert_next(&mut wx, 64);
+    tx.push_bytes(Bytes::from((0..64u8).collect::<Vec<_>>())).unwrap();
+    rx.set_read_staging(64);
+
+    let mut buf = [0; 1];
+    let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_read_into(&mut buf)));
+    assert_eq!(read.poll_future_notify(&notify_panic(), 0), Ok(Async::Ready(1)));
+    drop(read);
+    assert_eq!(buf[0], 0);
+
+    // Once the first read has staged the whole buffer, the channel is not polled again
+    // until the staged bytes have been read.
+    tx.reset("boom");
+    for i in 1..64 {
+        let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_read_into(&mut buf)));
+        assert_eq!(read.poll_future_notify(&notify_panic(), 0), Ok(Async::Ready(1)));
+        drop(read);
+        assert_eq!(buf[0], i);
+    }
+
+    let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_read_into(&mut buf)));
+    assert_eq!(read.poll_future_notify(&notify_panic(), 0), Err("boom"));
+}
+
+#[test]
+fn erased_receivers_share_a_type() {
+    use std::{error, fmt, io};
+
+    #[derive(Debug)]
+    struct Custom;
+    impl fmt::Display for Custom {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("custom")
+        }
+    }
+    impl error::Error for Custom {}
+
+    let (_wx0, tx0, rx0) = sync::new::<io::Error>(8);
+    let (_wx1, tx1, rx1) = sync::new::<Custom>(8);
+    let mut receivers: Vec<sync::ByteReceiver<Box<dyn error::Error + Send>>> =
+        vec![rx0.erase_error(), rx1.erase_error()];
+
+    tx0.reset(io::Error::other("io"));
+    tx1.reset(Custom);
+    let errors = receivers
+        .iter_mut()
+        .map(|rx| {
+            let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(8)));
+            match read.poll_future_notify(&notify_panic(), 0) {
+                Err(e) => e.to_string(),
+                res => panic!("expected error: {:?}", res.map(|_| ())),
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec!["io", "custom"]);
+}
+
+#[test]
+fn erased_receiver_reads_and_cancels_channel() {
+    use std::{fmt, io};
+
+    let (mut wx, mut tx, rx) = sync::new::<io::Error>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    let mut rx = rx.erase_error();
+    tx.push_bytes(Bytes::from("45")).unwrap();
+
+    let mut read = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(8)));
+    match read.poll_future_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.to_bytes(), Bytes::from("012345")),
+        res => panic!("expected chunk: {:?}", res.map(|_| ())),
+    }
+    drop(read);
+    sassert_next(&mut wx, 6);
+
+    rx.cancel(Box::new(io::Error::other("stop")));
+    match tx.push_bytes(Bytes::from("6")) {
+        Err(PushError::Cancelled) => {}
+        res => panic!("expected cancellation: {:?}", res),
+    }
+    assert_eq!(tx.take_cancellation().map(|e| e.to_string()), Some("stop".to_string()));
+
+    // The sender can't take an error of another type, but still sees the cancellation.
+    let (_wx, mut tx, rx) = sync::new::<io::Error>(8);
+    rx.erase_error().cancel(Box::new(fmt::Error));
+    match tx.push_bytes(Bytes::from("0")) {
+        Err(PushError::Cancelled) => {}
+        res => panic!("expected cancellation: {:?}", res),
+    }
+    assert!(tx.take_cancellation().is_none());
+}
+
+#[test]
+fn close_via_one_clone_then_drop_another() {
+    let (mut wx, mut tx0, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let tx1 = tx0.clone();
+    let tx2 = tx0.clone();
+    tx0.push_bytes(Bytes::from("0123")).unwrap();
+
+    let (counter, notify) = notify_count();
+    let mut task = executor::spawn(futures::future::poll_fn(|| rx.poll_chunk(8)));
+    match task.poll_future_notify(&notify, 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"0123"),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert!(task.poll_future_notify(&notify, 0).unwrap().is_not_ready());
+
+    tx1.close();
+    assert_eq!(counter.get(), 1);
+    drop(tx2);
+    drop(tx0);
+    assert_eq!(counter.get(), 1);
+
+    match task.poll_future_notify(&notify, 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+    assert_eq!(counter.get(), 1);
+}
+
+#[test]
+fn capacity_low_water_coalesces_sender_wakeups() {
+    let (mut tx, rx) = sync::bounded::<()>(8);
+    tx.set_capacity_low_water(4);
+    tx.push_bytes(Bytes::from("01234567")).unwrap();
+
+    let (counter, notify) = notify_count();
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+
+    let mut rx = executor::spawn(Reader(rx, 2));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => drop(c),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(counter.get(), 0);
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => drop(c),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(counter.get(), 1);
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::Ready(4)));
+}
+
+#[test]
+fn capacity_low_water_wakes_once_drained() {
+    let (mut tx, rx) = sync::bounded::<()>(8);
+    tx.set_capacity_low_water(16);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    let (counter, notify) = notify_count();
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+
+    let mut rx = executor::spawn(Reader(rx, 2));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => drop(c),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(counter.get(), 0);
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => drop(c),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(counter.get(), 1);
+    assert_eq!(capacity.poll_future_notify(&notify, 0), Ok(Async::Ready(8)));
+}
+
+#[test]
+fn probe_overdraws_zero_window() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(2);
+    sassert_next(&mut wx, 2);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    assert_eq!(tx.available_window(), 0);
+
+    tx.probe(b'x').unwrap();
+    assert_eq!(tx.len(), 3);
+    assert_eq!(
+        wx.snapshot(),
+        WindowSnapshot {
+            advertised: 0,
+            pending: 0,
+            underflow: 1,
+        }
+    );
+
+    // Reading the probe repays the overdraft before capacity is advertised again.
+    let mut rx = executor::spawn(Reader(rx, 1));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"0"),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_empty(&mut wx);
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.bytes(), b"1"),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut wx, 1);
+}
+
+#[test]
+fn stream_fn_collects_bounded_chunks() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("4567")).unwrap();
+    tx.close();
+
+    let chunks = sync::stream_fn(rx, 3).collect().wait().unwrap();
+    let lens = chunks.iter().map(|c| c.len()).collect::<Vec<_>>();
+    assert_eq!(lens, vec![3, 3, 2]);
+    let bytes = chunks.into_iter().flat_map(|c| c.collect::<Vec<u8>>()).collect::<Vec<_>>();
+    assert_eq!(&bytes[..], b"01234567");
+}
+
+#[test]
+fn advertiser_wakes_when_receiver_dropped_with_buffered_data() {
+    let (wx, mut tx, rx) = sync::new::<()>(8);
+    let mut wx = executor::spawn(wx);
+    let (count, notify) = notify_count();
+    assert_eq!(wx.poll_stream_notify(&notify, 0), Ok(Async::Ready(Some(8))));
+    tx.push_bytes(Bytes::from("012345")).unwrap();
+    assert_eq!(wx.poll_stream_notify(&notify, 0), Ok(Async::NotReady));
+    assert_eq!(count.get(), 0);
+
+    drop(rx);
+    assert!(count.get() > 0);
+    assert_eq!(wx.poll_stream_notify(&notify, 0), Ok(Async::Ready(Some(6))));
+    assert_eq!(wx.poll_stream_notify(&notify, 0), Ok(Async::Ready(None)));
+}
+
+#[test]
+fn metadata_is_ordered_with_data() {
+    let (mut wx, mut tx, mut rx) = sync::with_meta::<(), &'static str>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_meta("start").unwrap();
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_meta("middle").unwrap();
+    tx.push_bytes(Bytes::from("4567")).unwrap();
+    tx.push_meta("end").unwrap();
+    tx.close();
+
+    let mut events = Vec::new();
+    let mut read = executor::spawn(futures::future::poll_fn(|| loop {
+        if let Async::Ready(meta) = rx.poll_meta() {
+            match meta {
+                Some(meta) => events.push(meta.to_owned()),
+                None => return Ok::<_, ()>(Async::Ready(())),
+            }
+            continue;
+        }
+        match rx.poll_chunk(usize::MAX) {
+            Ok(Async::Ready(Some(chunk))) => {
+                assert!(!chunk.is_empty(), "metadata must be taken first");
+                events.push(String::from_utf8(chunk.collect()).unwrap());
+            }
+            // Once the data has ended, `poll_meta` reports the end of the metadata.
+            Ok(Async::Ready(None)) => {}
+            res => panic!("unexpected poll: {:?}", res),
+        }
+    }));
+    assert_eq!(read.poll_future_notify(&notify_panic(), 0), Ok(Async::Ready(())));
+    drop(read);
+    assert_eq!(events, vec!["start", "0123", "middle", "4567", "end"]);
+}
+
+#[test]
+fn chunk_reads_stop_at_metadata() {
+    let (mut wx, mut tx, mut rx) = sync::with_meta::<(), u32>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_meta(1).unwrap();
+    tx.push_bytes(Bytes::from("23")).unwrap();
+
+    assert_eq!(rx.poll_meta(), Async::NotReady);
+    match rx.poll_chunk(usize::MAX) {
+        Ok(Async::Ready(Some(chunk))) => assert_eq!(chunk.len(), 2),
+        res => panic!("unexpected poll: {:?}", res),
+    }
+    match rx.poll_chunk(usize::MAX) {
+        Ok(Async::Ready(Some(chunk))) => assert!(chunk.is_empty()),
+        res => panic!("unexpected poll: {:?}", res),
+    }
+    assert_eq!(rx.poll_meta(), Async::Ready(Some(1)));
+    match rx.poll_chunk(usize::MAX) {
+        Ok(Async::Ready(Some(chunk))) => assert_eq!(chunk.len(), 2),
+        res => panic!("unexpected poll: {:?}", res),
+    }
+}
+
+#[test]
+fn metadata_wakes_waiting_receiver() {
+    let (mut wx, mut tx, rx) = sync::with_meta::<(), u32>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = executor::spawn(rx);
+    let (count, notify) = notify_count();
+    let polled = rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk(usize::MAX).map(|a| a.map(|_| ())));
+    assert_eq!(polled, Ok(Async::NotReady));
+
+    tx.push_meta(7).unwrap();
+    assert_eq!(count.get(), 1);
+    assert_eq!(rx.get_mut().poll_meta(), Async::Ready(Some(7)));
+}
+
+#[test]
+fn metadata_wakes_task_waiting_for_metadata() {
+    let (mut wx, mut tx, rx) = sync::with_meta::<(), u32>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = executor::spawn(rx);
+    let (count, notify) = notify_count();
+    let mut poll_meta = || rx.poll_fn_notify(&notify, 0, |rx| Ok::<_, ()>(rx.poll_meta()));
+    assert_eq!(poll_meta(), Ok(Async::NotReady));
+
+    tx.push_meta(7).unwrap();
+    assert_eq!(count.get(), 1);
+    assert_eq!(poll_meta(), Ok(Async::Ready(Some(7))));
+
+    // The end of the stream must be read with `poll_chunk`, so it wakes the task too.
+    assert_eq!(poll_meta(), Ok(Async::NotReady));
+    tx.close();
+    assert_eq!(count.get(), 2);
+}
+
+#[test]
+fn watchdog_flags_parked_sender_and_receiver() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+    let mut watchdog = sync::Watchdog::new(&wx, &tx, &rx);
+    assert_eq!(watchdog.check_deadlock(), Ok(()));
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    let mut rx = executor::spawn(Reader(rx, 4));
+    match rx.poll_stream_notify(&notify_noop(), 0) {
+        Ok(Async::Ready(Some(c))) => drop(c),
+        res => panic!("stream error: {:?}", res),
+    }
+
+    // The returned credit is pending, but the advertiser isn't polled.
+    assert!(rx.poll_stream_notify(&notify_noop(), 0).unwrap().is_not_ready());
+    assert_eq!(watchdog.check_deadlock(), Ok(()));
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify_noop(), 0), Ok(Async::NotReady));
+    let first = watchdog.check_deadlock().unwrap_err();
+    let second = watchdog.check_deadlock().unwrap_err();
+    assert!(first.parked_for <= second.parked_for);
+
+    sassert_next(&mut wx, 4);
+    assert_eq!(watchdog.check_deadlock(), Ok(()));
+}
+
+#[test]
+fn watchdog_flags_credit_held_by_parked_advertiser() {
+    let (mut wx, mut tx, rx) = sync::Builder::new()
+        .window_size(4)
+        .notify_threshold(NotifyThreshold::Bytes(4))
+        .build::<()>();
+    sassert_next(&mut wx, 4);
+    let mut watchdog = sync::Watchdog::new(&wx, &tx, &rx);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    let mut rx = executor::spawn(Reader(rx, 4));
+    let mut chunk = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    sassert_empty(&mut wx);
+
+    // The receiver holds the rest of the chunk rather than waiting for data, and the
+    // returned credit is not published.
+    chunk.advance(2);
+    let mut capacity = executor::spawn(futures::future::poll_fn(|| tx.poll_capacity()));
+    assert_eq!(capacity.poll_future_notify(&notify_noop(), 0), Ok(Async::NotReady));
+    assert!(watchdog.check_deadlock().is_err());
+
+    drop(chunk);
+    sassert_next(&mut wx, 4);
+    assert_eq!(watchdog.check_deadlock(), Ok(()));
+}
+
+#[test]
+fn push_slice_copies_through_pool() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(64);
+    sassert_next(&mut wx, 64);
+    tx.set_copy_pool_size(8);
+    tx.push_slice(b"012").unwrap();
+    tx.push_slice(b"345").unwrap();
+    // Doesn't fit in the remaining pool, so a new backing buffer is used.
+    tx.push_slice(b"6789").unwrap();
+    // Larger than the pool, so it's copied on its own.
+    tx.push_slice(b"abcdefghij").unwrap();
+    tx.close();
+
+    let mut rx = executor::spawn(Reader(rx, 64));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.collect::<Vec<_>>(), b"0123456789abcdefghij"),
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn poll_readable_waits_without_reading() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = executor::spawn(rx);
+    let (count, notify) = notify_count();
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::NotReady);
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(count.get(), 1);
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::Ready(()));
+    assert_eq!(tx.len(), 4);
+
+    // Choose the read size once data is available.
+    let max_sz = tx.len() / 2;
+    match rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk(max_sz)) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.collect::<Vec<_>>(), b"01"),
+        res => panic!("unexpected poll: {:?}", res),
+    }
+
+    tx.close();
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::Ready(()));
+}
+
+#[test]
+fn poll_readable_waits_for_an_aligned_chunk() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = executor::spawn(rx);
+    rx.get_mut().set_chunk_alignment(4);
+    let (count, notify) = notify_count();
+
+    // A read would return nothing until a whole aligned chunk is buffered.
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::NotReady);
+    tx.push_bytes(Bytes::from("23")).unwrap();
+    assert_eq!(count.get(), 1);
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::Ready(()));
+    match rx.poll_fn_notify(&notify_panic(), 0, |rx| rx.poll_chunk(8)) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.collect::<Vec<_>>(), b"0123"),
+        res => panic!("unexpected poll: {:?}", res),
+    }
+
+    // Once the sender has closed, a short tail is readable.
+    tx.push_bytes(Bytes::from("4")).unwrap();
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::NotReady);
+    tx.close();
+    assert_eq!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_readable()), Async::Ready(()));
+}
+
+#[test]
+fn donate_window_moves_available_credit() {
+    let (mut awx, mut atx, _arx) = sync::new::<()>(8);
+    let (mut bwx, btx, brx) = sync::new::<()>(2);
+    sassert_next(&mut awx, 8);
+    sassert_next(&mut bwx, 2);
+    atx.push_bytes(Bytes::from("012")).unwrap();
+
+    assert_eq!(atx.donate_window(&btx, 3), 3);
+    assert_eq!(atx.available_window(), 2);
+    assert_eq!(btx.available_window(), 5);
+
+    // Only the available window may be donated.
+    assert_eq!(atx.donate_window(&btx, 8), 2);
+    assert_eq!(atx.available_window(), 0);
+    assert_eq!(btx.donate_window(&atx, 1), 1);
+    assert_eq!(atx.available_window(), 1);
+    assert_eq!(btx.available_window(), 6);
+
+    // Donated credit is returned to the recipient once consumed.
+    let mut btx = btx;
+    btx.push_bytes(Bytes::from("012345")).unwrap();
+    let mut brx = executor::spawn(Reader(brx, 8));
+    match brx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.len(), 6),
+        res => panic!("stream error: {:?}", res),
+    }
+    sassert_next(&mut bwx, 6);
+}
+
+#[test]
+fn poll_result_reports_chunks_end_and_errors() {
+    let (mut wx, mut tx, rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = executor::spawn(rx);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    match rx.poll_fn_notify(&notify_noop(), 0, |rx| rx.poll_result(8)) {
+        Ok(Async::Ready(sync::ChunkOrEnd::Chunk(c))) => assert_eq!(c.len(), 2),
+        res => panic!("unexpected poll: {:?}", res),
+    }
+    tx.close();
+    match rx.poll_fn_notify(&notify_noop(), 0, |rx| rx.poll_result(8)) {
+        Ok(Async::Ready(sync::ChunkOrEnd::End)) => {}
+        res => panic!("unexpected poll: {:?}", res),
+    }
+
+    let (mut wx, tx, rx) = sync::new::<&'static str>(8);
+    sassert_next(&mut wx, 8);
+    let mut rx = executor::spawn(rx);
+    tx.reset("failed");
+    match rx.poll_fn_notify(&notify_noop(), 0, |rx| rx.poll_result(8)) {
+        Ok(Async::Ready(sync::ChunkOrEnd::Error("failed"))) => {}
+        res => panic!("unexpected poll: {:?}", res),
+    }
+}
+
+#[test]
+fn push_from_feeds_source_without_copying() {
+    const WINDOW: usize = 64 * 1024;
+    let (mut wx, mut tx, rx) = sync::new::<()>(WINDOW);
+    let mut source = Bytes::from(vec![7u8; 1024 * 1024]);
+    let start = source.as_ptr() as usize;
+    let end = start + source.len();
+
+    let mut rx = executor::spawn(Reader(rx, usize::MAX));
+    let mut received = 0;
+    while !source.is_empty() {
+        sassert_next(&mut wx, WINDOW);
+        assert_eq!(tx.push_from(&mut source, usize::MAX), Ok(WINDOW));
+        assert_eq!(tx.push_from(&mut source, usize::MAX), Ok(0));
+
+        match rx.poll_stream_notify(&notify_panic(), 0) {
+            Ok(Async::Ready(Some(c))) => {
+                let ptr = c.bytes().as_ptr() as usize;
+                assert!(start <= ptr && ptr + c.len() <= end, "chunk must alias the source");
+                received += c.len();
+            }
+            res => panic!("stream error: {:?}", res),
+        }
+    }
+    assert_eq!(received, 1024 * 1024);
+}
+
+#[test]
+fn warn_buffered_above_fires_when_crossed() {
+    static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+    fn warn(len: usize) {
+        assert_eq!(len, 6);
+        WARNINGS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let (mut wx, mut tx, _rx) = sync::Builder::new()
+        .window_size(16)
+        .warn_buffered_above(4, warn)
+        .build::<()>();
+    sassert_next(&mut wx, 16);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(WARNINGS.load(Ordering::SeqCst), 0);
+    tx.push_bytes(Bytes::from("45")).unwrap();
+    assert_eq!(WARNINGS.load(Ordering::SeqCst), 1);
+    tx.push_bytes(Bytes::from("6789")).unwrap();
+    assert_eq!(WARNINGS.load(Ordering::SeqCst), 1);
+}
+
+/// Collects items, accepting at most `capacity` items between flushes.
+struct VecSink {
+    items: Vec<Bytes>,
+    unflushed: usize,
+    capacity: usize,
+}
+
+impl futures::Sink for VecSink {
+    type SinkItem = Bytes;
+    type SinkError = ();
+
+    fn start_send(&mut self, item: Bytes) -> futures::StartSend<Bytes, ()> {
+        if self.unflushed == self.capacity {
+            return Ok(futures::AsyncSink::NotReady(item));
+        }
+        self.unflushed += 1;
+        self.items.push(item);
+        Ok(futures::AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), ()> {
+        self.unflushed = 0;
+        Ok(Async::Ready(()))
+    }
+}
+
+#[test]
+fn forward_into_feeds_sink() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let sink = VecSink {
+        items: Vec::new(),
+        unflushed: 0,
+        capacity: 1,
+    };
+    let mut forward = executor::spawn(rx.forward_into(sink));
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.push_bytes(Bytes::from("45")).unwrap();
+    tx.push_bytes(Bytes::from("67")).unwrap();
+    assert!(forward.poll_future_notify(&notify_noop(), 0).unwrap().is_not_ready());
+    sassert_next(&mut wx, 8);
+
+    tx.push_bytes(Bytes::from("89")).unwrap();
+    tx.close();
+    let sink = match forward.poll_future_notify(&notify_noop(), 0) {
+        Ok(Async::Ready(sink)) => sink,
+        res => panic!("forward error: {:?}", res.map(|_| ())),
+    };
+    assert_eq!(sink.items, vec!["0123", "45", "67", "89"]);
+}
+
+#[test]
+fn window_observer_does_not_prevent_orphaning() {
+    let (mut wx, mut tx, rx) = sync::Builder::new()
+        .window_size(8)
+        .collect_stats(true)
+        .build::<()>();
+    let observer = rx.window_observer();
+    let watchdog = sync::Watchdog::new(&wx, &tx, &rx);
+    assert_eq!(observer.pending(), 8);
+    sassert_next(&mut wx, 8);
+    assert_eq!((observer.available(), observer.pending()), (8, 0));
+
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    assert_eq!(observer.available(), 4);
+    assert_eq!(observer.stats().map(|s| s.bytes_pushed), Some(4));
+
+    let mut rx = executor::spawn(Reader(rx, 8));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => drop(c),
+        res => panic!("stream error: {:?}", res),
+    }
+    assert_eq!(observer.pending(), 4);
+    sassert_next(&mut wx, 4);
+
+    // Observers don't keep the channel alive.
+    let observer2 = observer.clone();
+    drop((tx, rx));
+    sassert_done(&mut wx);
+    drop(wx);
+    assert_eq!((observer2.available(), observer2.pending()), (0, 0));
+    drop((observer, watchdog));
+}
+
+#[test]
+fn max_message_rejects_large_pushes() {
+    let (mut wx, mut tx, _rx) = sync::Builder::new()
+        .window_size(16)
+        .max_message(4)
+        .build::<()>();
+    sassert_next(&mut wx, 16);
+    assert_eq!(tx.push_bytes(Bytes::from("01234")), Err(PushError::MessageTooLarge));
+    assert_eq!(tx.available_window(), 16);
+    assert_eq!(tx.push_bytes(Bytes::from("0123")), Ok(4));
+}
+
+#[derive(Debug, PartialEq)]
+enum BodyError {
+    TooLarge,
+}
+
+impl From<LimitExceeded> for BodyError {
+    fn from(_: LimitExceeded) -> Self {
+        BodyError::TooLarge
+    }
+}
+
+fn limited_body(body: &'static str, max_total: u64) -> Result<Vec<u8>, BodyError> {
+    let (mut wx, mut tx, rx) = sync::new::<BodyError>(64);
+    sassert_next(&mut wx, 64);
+    for part in body.as_bytes().chunks(3) {
+        tx.push_bytes(Bytes::from(part)).unwrap();
+    }
+    tx.close();
+
+    let mut received = Vec::new();
+    let mut rx = rx.limit(max_total);
+    loop {
+        match rx.poll_chunk(4) {
+            Ok(Async::Ready(Some(c))) => received.extend(c.collect::<Vec<u8>>()),
+            Ok(Async::Ready(None)) => return Ok(received),
+            Ok(Async::NotReady) => panic!("closed channel must be ready"),
+            Err(e) => {
+                // The error is sticky.
+                assert_eq!(rx.poll_chunk(4).err(), Some(BodyError::TooLarge));
+                assert_eq!(received.len() as u64, max_total);
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[test]
+fn limit_allows_bodies_within_limit() {
+    assert_eq!(limited_body("0123456789", 10), Ok(b"0123456789".to_vec()));
+    assert_eq!(limited_body("0123456789", 11), Ok(b"0123456789".to_vec()));
+}
+
+#[test]
+fn limit_fails_bodies_over_limit() {
+    assert_eq!(limited_body("0123456789", 9), Err(BodyError::TooLarge));
+    assert_eq!(limited_body("0123456789", 0), Err(BodyError::TooLarge));
+}
+
+#[test]
+fn half_close_is_observed_by_sender() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    rx.half_close();
+    assert_eq!(tx.push_bytes(Bytes::from("45")), Err(PushError::ReceiverDone));
+    assert_eq!(tx.take_cancellation(), None);
+
+    // The discarded data is credited, and the receiver reads nothing more.
+    sassert_next(&mut wx, 4);
+    let mut rx = executor::spawn(Reader(rx, 8));
+    match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(None)) => {}
+        res => panic!("stream error: {:?}", res),
+    }
+}
+
+#[test]
+fn half_close_wakes_sender_awaiting_drain() {
+    let (mut wx, tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let mut tx = executor::spawn(tx);
+    tx.get_mut().push_bytes(Bytes::from("0123")).unwrap();
+
+    // The receiver holds a chunk, so the channel can't drain.
+    let mut rx = executor::spawn(Reader(rx, 8));
+    let chunk = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    let (count, notify) = notify_count();
+    assert_eq!(tx.poll_fn_notify(&notify, 0, |tx| tx.poll_drained()), Ok(Async::NotReady));
+
+    rx.get_mut().0.half_close();
+    assert_eq!(count.get(), 1);
+    assert_eq!(
+        tx.poll_fn_notify(&notify_panic(), 0, |tx| tx.poll_drained()),
+        Err(PushError::ReceiverDone)
+    );
+    drop(chunk);
+}
+
+/// Pushes segments of `push_sizes` through a bounded channel while reading with
+/// `reads`, each a read size and a step by which the chunk is advanced, and checks
+/// that the data and the window survive intact.
+fn split_and_advance_preserve_data_and_window(push_sizes: Vec<u8>, reads: Vec<(u8, u8)>) -> bool {
+    const CAPACITY: usize = 64;
+    let (mut tx, rx) = sync::bounded::<()>(CAPACITY);
+    let mut rx = executor::spawn(rx);
+
+    let mut pushed = Vec::new();
+    let mut received = Vec::new();
+    let mut push_sizes = push_sizes.into_iter().map(|sz| 1 + sz as usize % CAPACITY);
+    let mut reads = reads.into_iter().cycle().map(|(sz, step)| (1 + sz as usize, 1 + step as usize));
+    let mut next_push = push_sizes.next();
+    if next_push.is_none() {
+        tx.clone().close();
+    }
+    loop {
+        if let Some(sz) = next_push {
+            if sz <= tx.available_window() {
+                let bytes = (0..sz).map(|i| (pushed.len() + i) as u8).collect::<Vec<_>>();
+                pushed.extend_from_slice(&bytes);
+                tx.push_bytes(Bytes::from(bytes)).unwrap();
+                next_push = push_sizes.next();
+                if next_push.is_none() {
+                    tx.clone().close();
+                }
+                continue;
+            }
+        }
+
+        // Only read when the read can't wait: either data is buffered or the channel
+        // has been closed.
+        let (max_sz, step) = reads.next().unwrap_or((CAPACITY, CAPACITY));
+        match rx.poll_fn_notify(&notify_noop(), 0, |rx| rx.poll_chunk(max_sz)) {
+            Ok(Async::Ready(Some(mut chunk))) => {
+                assert!(!chunk.is_empty() && chunk.len() <= max_sz);
+                while chunk.has_remaining() {
+                    let sz = std::cmp::min(step, chunk.bytes().len());
+                    received.extend_from_slice(&chunk.bytes()[..sz]);
+                    chunk.advance(sz);
+                }
+            }
+            Ok(Async::Ready(None)) => break,
+            res => panic!("unexpected poll: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+        }
+    }
+
+    received == pushed && tx.available_window() == CAPACITY && tx.is_empty()
+}
+
+#[test]
+fn chunk_split_and_advance_invariants() {
+    quickcheck::QuickCheck::new()
+        .tests(500)
+        .quickcheck(split_and_advance_preserve_data_and_window as fn(Vec<u8>, Vec<(u8, u8)>) -> bool);
+}
+
+#[test]
+fn poll_drained_waits_for_held_chunks() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+
+    let (count, notify) = notify_count();
+    let mut drained = executor::spawn(futures::future::poll_fn(|| tx.poll_drained()));
+    assert_eq!(drained.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+
+    let mut rx = executor::spawn(Reader(rx, 8));
+    let chunk = match rx.poll_stream_notify(&notify_panic(), 0) {
+        Ok(Async::Ready(Some(c))) => c,
+        res => panic!("stream error: {:?}", res),
+    };
+    assert_eq!(count.get(), 0);
+    assert_eq!(drained.poll_future_notify(&notify, 0), Ok(Async::NotReady));
+
+    drop(chunk);
+    assert_eq!(count.get(), 1);
+    assert_eq!(drained.poll_future_notify(&notify, 0), Ok(Async::Ready(())));
+    drop(drained);
+
+    drop(rx);
+    let mut drained = executor::spawn(futures::future::poll_fn(|| tx.poll_drained()));
+    assert_eq!(drained.poll_future_notify(&notify, 0), Err(PushError::LostReceiver));
+}
+
+#[test]
+fn lowering_chunk_alignment_wakes_parked_receiver() {
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    rx.set_chunk_alignment(4);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+
+    let (count, notify) = notify_count();
+    let mut rx = executor::spawn(rx);
+    assert!(rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk(8)).unwrap().is_not_ready());
+
+    // Raising the requirement leaves the receiver parked.
+    rx.get_mut().set_chunk_alignment_and_wake(6);
+    assert_eq!(count.get(), 0);
+
+    rx.get_mut().set_chunk_alignment_and_wake(2);
+    assert_eq!(count.get(), 1);
+    assert_eq!(tx.preferred_push_size(), Some(4));
+    match rx.poll_fn_notify(&notify, 0, |rx| rx.poll_chunk(8)) {
+        Ok(Async::Ready(Some(c))) => assert_eq!(c.len(), 2),
+        res => panic!("unexpected poll: {:?}", res.map(|a| a.map(|c| c.map(|c| c.len())))),
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn complete_channel_serializes_its_body() {
+    extern crate serde_json;
+
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    tx.push_bytes(Bytes::from("234")).unwrap();
+    assert_eq!(rx.to_bytes_if_complete(), None);
+    assert!(serde_json::to_vec(&rx).is_err());
+
+    tx.close();
+    assert_eq!(rx.to_bytes_if_complete(), Some(Bytes::from("01234")));
+    let json = serde_json::to_vec(&rx).unwrap();
+    let body: Vec<u8> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(body, b"01234");
+}
+
+#[cfg(feature = "record")]
+#[test]
+fn recorded_operations_replay() {
+    let (mut wx, mut tx, rx) = sync::new::<()>(10);
+    let mut rx = Reader(rx, 0);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("0123456789")).unwrap();
+
+    for sz in &[4_usize, 3, 2, 1] {
+        let sz = *sz;
+        rx = rx.resize(sz);
+        let mut chunk = match executor::spawn(&mut rx).poll_stream_notify(&notify_panic(), 0) {
+            Ok(Async::Ready(Some(c))) => c,
+            res => panic!("stream error: {:?}", res),
+        };
+        chunk.advance(1);
+        drop(chunk);
+        sassert_next(&mut wx, sz);
+        tx.push_bytes(Bytes::from(vec![0; sz])).unwrap();
+    }
+    rx.shrink_window(2);
+    rx.0.grow_window(3);
+    sassert_next(&mut wx, 1);
+
+    let ops = rx.0.recorded_ops();
+    // The 1-byte chunk is fully advanced before it is dropped, so its drop returns nothing.
+    assert_eq!(ops.len(), 3 + 4 * 5 - 1 + 3);
+    assert_eq!(ops[0].op, byte_channel::Op::Open { window_size: 10, burst_size: 10 });
+    assert_eq!(ops.last().unwrap().op, byte_channel::Op::Increment(1));
+    sync::replay(&ops);
+}
+
+#[test]
+fn broadcast_delivers_to_every_receiver() {
+    let (mut tx, mut rxs) = sync::broadcast::new::<()>(4, 2);
+    assert_eq!(tx.receivers(), 2);
+    assert_eq!(tx.available_window(), 4);
+
+    tx.push_bytes(Bytes::from("012")).unwrap();
+    assert_eq!(tx.available_window(), 1);
+    let mut slow = rxs.pop().unwrap();
+    let mut fast = rxs.pop().unwrap();
+    assert_eq!(fast.try_recv(8).unwrap().bytes(), b"012");
+
+    // The slow receiver holds back the sender until it consumes its data.
+    assert_eq!(tx.available_window(), 1);
+    let held = slow.try_recv(8).unwrap();
+    assert_eq!(held.bytes(), b"012");
+    assert_eq!(tx.available_window(), 1);
+    drop(held);
+    assert_eq!(tx.available_window(), 4);
+
+    // A dropped receiver no longer limits the window.
+    tx.push_bytes(Bytes::from("3456")).unwrap();
+    drop(slow);
+    assert_eq!(tx.receivers(), 1);
+    assert_eq!(tx.available_window(), 0);
+    assert_eq!(fast.try_recv(8).unwrap().bytes(), b"3456");
+    assert_eq!(tx.available_window(), 4);
+
+    tx.close();
+    assert_eq!(fast.try_recv(8).err(), Some(sync::TryRecvError::Closed));
+}
+
+#[test]
+fn broadcast_fails_once_all_receivers_dropped() {
+    let (mut tx, rxs) = sync::broadcast::new::<()>(4, 2);
+    drop(rxs);
+    assert_eq!(tx.receivers(), 0);
+    assert_eq!(tx.push_bytes(Bytes::from("0")), Err(PushError::LostReceiver));
+}
+
+#[test]
+fn broadcast_push_to_cancelled_receiver_delivers_nothing() {
+    let (mut tx, mut rxs) = sync::broadcast::new::<()>(4, 2);
+    let mut cancelled = rxs.pop().unwrap();
+    let mut open = rxs.pop().unwrap();
+    cancelled.cancel(());
+
+    assert_eq!(tx.push_bytes(Bytes::from("012")), Err(PushError::Cancelled));
+    assert_eq!(open.try_recv(8).err(), Some(sync::TryRecvError::Empty));
+    assert_eq!(tx.available_window(), 4);
+}
+
+/// Reads from `reader` within a task, so that a read that would block may park.
+fn read_in_task<R: std::io::Read>(reader: &mut R, dst: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = executor::spawn(futures::future::lazy(|| Ok::<_, ()>(reader.read(dst))));
+    match read.poll_future_notify(&notify_noop(), 0) {
+        Ok(Async::Ready(res)) => res,
+        res => panic!("read did not complete: {:?}", res),
+    }
+}
+
+#[test]
+fn async_reader_returns_capacity_as_bytes_are_copied() {
+    use std::io;
+
+    let (mut wx, mut tx, mut rx) = sync::new::<io::Error>(10);
+    sassert_next(&mut wx, 10);
+    tx.push_bytes(Bytes::from("01234")).unwrap();
+    tx.push_bytes(Bytes::from("56789")).unwrap();
+    rx.set_read_staging(8);
+    let mut reader = rx.into_async_read();
+
+    // Bytes left over from a staged chunk are served by later reads.
+    let mut buf = [0; 3];
+    assert_eq!(read_in_task(&mut reader, &mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"012");
+    sassert_next(&mut wx, 3);
+    assert_eq!(read_in_task(&mut reader, &mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"345");
+    sassert_next(&mut wx, 3);
+    assert_eq!(read_in_task(&mut reader, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"67");
+    sassert_next(&mut wx, 2);
+    assert_eq!(read_in_task(&mut reader, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"89");
+    sassert_next(&mut wx, 2);
+
+    let err = read_in_task(&mut reader, &mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    tx.reset(io::Error::other("boom"));
+    let err = read_in_task(&mut reader, &mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert_eq!(err.to_string(), "boom");
+}
+
+#[test]
+fn async_reader_reads_zero_at_end_of_stream() {
+    use std::io;
+
+    let (mut wx, mut tx, rx) = sync::new::<io::Error>(4);
+    sassert_next(&mut wx, 4);
+    let mut reader = rx.into_async_read();
+    tx.push_bytes(Bytes::from("0123")).unwrap();
+    tx.close();
+
+    let mut buf = [0; 8];
+    assert_eq!(read_in_task(&mut reader, &mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"0123");
+    assert_eq!(read_in_task(&mut reader, &mut buf).unwrap(), 0);
+}
+
+/// Writes to `writer` within a task, so that a write that would block may park.
+fn write_in_task<W: std::io::Write>(writer: &mut W, src: &[u8]) -> std::io::Result<usize> {
+    let mut write = executor::spawn(futures::future::lazy(|| Ok::<_, ()>(writer.write(src))));
+    match write.poll_future_notify(&notify_noop(), 0) {
+        Ok(Async::Ready(res)) => res,
+        res => panic!("write did not complete: {:?}", res),
+    }
+}
+
+#[test]
+fn async_writer_accepts_what_the_window_allows() {
+    use std::io::{self, Write};
+
+    let (mut wx, tx, mut rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+    let mut writer = tx.into_async_write();
+
+    assert_eq!(write_in_task(&mut writer, b"012345").unwrap(), 4);
+    let err = write_in_task(&mut writer, b"45").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    writer.flush().unwrap();
+
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"0123");
+    sassert_next(&mut wx, 4);
+    assert_eq!(write_in_task(&mut writer, b"45").unwrap(), 2);
+    assert_eq!(writer.get_ref().available_window(), 2);
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"45");
+
+    drop(rx);
+    let err = write_in_task(&mut writer, b"6").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[cfg(feature = "tokio-io")]
+#[test]
+fn async_writer_shutdown_closes_channel() {
+    use tokio_io::AsyncWrite;
+
+    let (mut wx, tx, mut rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+    let mut writer = tx.into_async_write();
+    assert_eq!(write_in_task(&mut writer, b"01").unwrap(), 2);
+    assert_eq!(writer.shutdown().unwrap(), Async::Ready(()));
+
+    assert_eq!(rx.try_recv(8).unwrap().bytes(), b"01");
+    assert_eq!(rx.try_recv(8).err(), Some(sync::TryRecvError::Closed));
+}
+
+#[cfg(feature = "futures03")]
+struct CountingWaker(AtomicUsize);
+
+#[cfg(feature = "futures03")]
+impl std::task::Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "futures03")]
+#[test]
+fn futures03_streams_wake_the_polling_task() {
+    use futures_core::Stream as Stream03;
+    use std::pin::Pin;
+    use std::task::{Context, Poll as Poll03, Waker};
+
+    let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(4);
+    match Pin::new(&mut wx).poll_next(&mut cx) {
+        Poll03::Ready(Some(4)) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+    assert!(Pin::new(&mut rx).poll_next(&mut cx).is_pending());
+
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    tx.push_bytes(Bytes::from("23")).unwrap();
+    let chunk = match Pin::new(&mut rx).poll_next(&mut cx) {
+        Poll03::Ready(Some(Ok(c))) => c,
+        res => panic!("expected chunk: {:?}", res.map(|r| r.map(|c| c.map(|c| c.len())))),
+    };
+    assert_eq!(chunk.bytes(), b"01");
+    assert_eq!(chunk.len(), 4);
+
+    // The advertiser is woken as the chunk returns capacity.
+    assert!(Pin::new(&mut wx).poll_next(&mut cx).is_pending());
+    drop(chunk);
+    assert_eq!(count.0.load(Ordering::SeqCst), 2);
+    match Pin::new(&mut wx).poll_next(&mut cx) {
+        Poll03::Ready(Some(4)) => {}
+        res => panic!("expected increment: {:?}", res),
+    }
+
+    tx.close();
+    assert!(Pin::new(&mut rx).poll_next(&mut cx).is_ready());
+    drop(rx);
+    match Pin::new(&mut wx).poll_next(&mut cx) {
+        Poll03::Ready(None) => {}
+        res => panic!("expected end: {:?}", res),
+    }
+}
+
+#[cfg(feature = "futures03")]
+#[test]
+fn futures03_chunk_stream_bounds_chunks() {
+    use futures_core::Stream as Stream03;
+    use std::pin::Pin;
+    use std::task::{Context, Poll as Poll03, Waker};
+
+    let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let (mut wx, mut tx, rx) = sync::new::<()>(8);
+    sassert_next(&mut wx, 8);
+    let mut chunks = rx.into_chunks(3);
+    tx.push_bytes(Bytes::from("01234")).unwrap();
+    tx.close();
+
+    let mut lens = vec![];
+    loop {
+        match Pin::new(&mut chunks).poll_next(&mut cx) {
+            Poll03::Ready(Some(Ok(c))) => lens.push(c.len()),
+            Poll03::Ready(None) => break,
+            res => panic!("unexpected poll: {:?}", res.map(|r| r.map(|c| c.map(|c| c.len())))),
+        }
+    }
+    assert_eq!(lens, vec![3, 2]);
+}
+
+#[cfg(feature = "futures03")]
+#[test]
+fn futures03_stream_wakes_the_latest_waker() {
+    use futures_core::Stream as Stream03;
+    use std::pin::Pin;
+    use std::task::{Context, Waker};
+
+    let first = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let first_waker = Waker::from(first.clone());
+    let second = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let second_waker = Waker::from(second.clone());
+
+    let (mut wx, mut tx, mut rx) = sync::new::<()>(4);
+    sassert_next(&mut wx, 4);
+    let mut cx = Context::from_waker(&first_waker);
+    assert!(Pin::new(&mut rx).poll_next(&mut cx).is_pending());
+    assert!(Pin::new(&mut rx).poll_next(&mut cx).is_pending());
+
+    // The receiver moves to another task, which must be the one woken.
+    let mut cx = Context::from_waker(&second_waker);
+    assert!(Pin::new(&mut rx).poll_next(&mut cx).is_pending());
+    tx.push_bytes(Bytes::from("01")).unwrap();
+    assert_eq!(first.0.load(Ordering::SeqCst), 0);
+    assert_eq!(second.0.load(Ordering::SeqCst), 1);
+}